@@ -0,0 +1,16 @@
+ALTER TABLE users ADD COLUMN email_verified_at TIMESTAMPTZ;
+
+-- accounts that already exist keep working
+UPDATE users SET email_verified_at = NOW();
+
+CREATE TYPE action_token_purpose AS ENUM ('password_reset', 'email_verification');
+
+-- links sent by mail, each one can be used only once
+CREATE TABLE action_tokens (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  purpose action_token_purpose NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL,
+  used_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,25 @@
+CREATE TYPE throttle_scope AS ENUM ('email', 'ip');
+
+-- failed logins per email address and per client ip
+CREATE TABLE login_throttles (
+  scope throttle_scope NOT NULL,
+  key VARCHAR(255) NOT NULL,
+  failures INTEGER NOT NULL DEFAULT 0,
+  last_failure_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  locked_until TIMESTAMPTZ,
+  PRIMARY KEY (scope, key)
+);
+
+CREATE TABLE lockouts (
+  id SERIAL PRIMARY KEY,
+  scope throttle_scope NOT NULL,
+  key VARCHAR(255) NOT NULL,
+  user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+  failures INTEGER NOT NULL,
+  locked_until TIMESTAMPTZ NOT NULL,
+  unlocked_at TIMESTAMPTZ,
+  unlocked_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX lockouts_user_id_idx ON lockouts (user_id);
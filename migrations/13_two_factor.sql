@@ -0,0 +1,28 @@
+ALTER TYPE action_token_purpose ADD VALUE 'two_factor_login';
+
+ALTER TABLE users
+  ADD COLUMN totp_secret VARCHAR(64),
+  ADD COLUMN totp_enabled_at TIMESTAMPTZ,
+  -- the last time step a code was accepted for, so codes can't be replayed
+  ADD COLUMN totp_last_step BIGINT;
+
+CREATE TABLE recovery_codes (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  code_hash VARCHAR(64) NOT NULL,
+  used_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX recovery_codes_user_id_idx ON recovery_codes (user_id);
+
+CREATE TABLE role_policies (
+  role user_role PRIMARY KEY,
+  require_two_factor BOOLEAN NOT NULL DEFAULT FALSE,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER update_me_daddy
+BEFORE UPDATE ON role_policies
+FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,30 @@
+CREATE TYPE permission AS ENUM (
+  'manage_users',
+  'manage_places',
+  'manage_items',
+  'move_stock',
+  'request_materials',
+  'approve_requisitions'
+);
+
+CREATE TABLE api_keys (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  name VARCHAR(255) NOT NULL,
+  -- the first characters of the key, so users can tell their keys apart
+  prefix VARCHAR(16) NOT NULL,
+  key_hash VARCHAR(64) NOT NULL UNIQUE,
+  scopes permission[] NOT NULL,
+  last_used_at TIMESTAMPTZ,
+  expires_at TIMESTAMPTZ,
+  revoked_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX api_keys_user_id_idx ON api_keys (user_id);
+
+CREATE TRIGGER update_me_daddy
+BEFORE UPDATE ON api_keys
+FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,25 @@
+-- accounts provisioned through single sign-on have no password of their own
+ALTER TABLE users ALTER COLUMN password DROP NOT NULL;
+
+-- logins sent to the identity provider and not back yet
+CREATE TABLE oidc_logins (
+  id SERIAL PRIMARY KEY,
+  state_hash VARCHAR(64) NOT NULL UNIQUE,
+  nonce VARCHAR(64) NOT NULL,
+  code_verifier VARCHAR(128) NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE identities (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  issuer VARCHAR(255) NOT NULL,
+  subject VARCHAR(255) NOT NULL,
+  email VARCHAR(255) NOT NULL,
+  last_login_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  UNIQUE (issuer, subject)
+);
+
+CREATE INDEX identities_user_id_idx ON identities (user_id);
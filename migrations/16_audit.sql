@@ -0,0 +1,45 @@
+ALTER TYPE permission ADD VALUE 'view_audit';
+
+CREATE TYPE audit_action AS ENUM ('create', 'update', 'delete');
+
+CREATE TYPE audit_entity AS ENUM (
+  'place',
+  'item',
+  'user',
+  'stock_movement',
+  'stock_transfer',
+  'loan',
+  'requisition',
+  'api_key'
+);
+
+-- who changed what and when, kept even after the actor or entity is gone
+CREATE TABLE audit_log (
+  id SERIAL PRIMARY KEY,
+  actor_id INTEGER,
+  action audit_action NOT NULL,
+  entity_type audit_entity NOT NULL,
+  entity_id INTEGER NOT NULL,
+  -- only the fields that changed, NULL before a create and after a delete
+  before JSONB,
+  after JSONB,
+  request_id VARCHAR(64),
+  ip VARCHAR(64),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX audit_log_entity_idx ON audit_log (entity_type, entity_id);
+CREATE INDEX audit_log_actor_id_idx ON audit_log (actor_id);
+CREATE INDEX audit_log_created_at_idx ON audit_log (created_at);
+
+CREATE OR REPLACE FUNCTION forbid_audit_change()
+RETURNS TRIGGER AS $$
+BEGIN
+  RAISE EXCEPTION 'the audit log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+BEFORE UPDATE OR DELETE ON audit_log
+FOR EACH ROW
+EXECUTE PROCEDURE forbid_audit_change();
@@ -0,0 +1,5 @@
+-- deleted rows are kept so the history pointing at them stays intact
+ALTER TABLE places ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMPTZ;
+
+ALTER TYPE audit_action ADD VALUE 'restore';
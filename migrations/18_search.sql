@@ -0,0 +1,36 @@
+CREATE EXTENSION IF NOT EXISTS unaccent;
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- unaccent() is only stable, which keeps it out of indexes
+CREATE OR REPLACE FUNCTION immutable_unaccent(TEXT)
+RETURNS TEXT AS $$
+  SELECT public.unaccent('public.unaccent', $1);
+$$ LANGUAGE sql IMMUTABLE PARALLEL SAFE STRICT;
+
+-- portuguese stemming that ignores accents, "almoxarifádo" finds "almoxarifado"
+CREATE TEXT SEARCH CONFIGURATION almox (COPY = portuguese);
+ALTER TEXT SEARCH CONFIGURATION almox
+  ALTER MAPPING FOR hword, hword_part, word WITH unaccent, portuguese_stem;
+
+-- what is searched in each table, the indexes below only help queries that
+-- call these very functions. Accents are stripped before parsing as well,
+-- since with a C locale the parser splits words on them.
+CREATE OR REPLACE FUNCTION place_document(name TEXT, description TEXT)
+RETURNS TSVECTOR AS $$
+  SELECT setweight(to_tsvector('almox', immutable_unaccent(name)), 'A') ||
+    setweight(to_tsvector('almox', immutable_unaccent(COALESCE(description, ''))), 'B');
+$$ LANGUAGE sql IMMUTABLE PARALLEL SAFE;
+
+CREATE OR REPLACE FUNCTION item_document(name TEXT, sku TEXT, description TEXT)
+RETURNS TSVECTOR AS $$
+  SELECT setweight(to_tsvector('almox', immutable_unaccent(name)), 'A') ||
+    setweight(to_tsvector('almox', sku), 'A') ||
+    setweight(to_tsvector('almox', immutable_unaccent(COALESCE(description, ''))), 'B');
+$$ LANGUAGE sql IMMUTABLE PARALLEL SAFE;
+
+CREATE INDEX places_search_idx ON places USING GIN (place_document(name, description));
+CREATE INDEX items_search_idx ON items USING GIN (item_document(name, sku, description));
+
+-- for typos, matched by trigram similarity against the name
+CREATE INDEX places_name_trgm_idx ON places USING GIN (immutable_unaccent(LOWER(name)) gin_trgm_ops);
+CREATE INDEX items_name_trgm_idx ON items USING GIN (immutable_unaccent(LOWER(name)) gin_trgm_ops);
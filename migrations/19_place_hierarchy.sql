@@ -0,0 +1,5 @@
+-- places nest into each other, building > room > shelf > bin
+ALTER TABLE places ADD COLUMN parent_id INTEGER REFERENCES places(id);
+ALTER TABLE places ADD CONSTRAINT places_parent_not_self CHECK (parent_id <> id);
+
+CREATE INDEX places_parent_id_idx ON places (parent_id);
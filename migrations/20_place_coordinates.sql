@@ -0,0 +1,11 @@
+-- where a place is on the map, in degrees (WGS 84)
+ALTER TABLE places ADD COLUMN lat DOUBLE PRECISION;
+ALTER TABLE places ADD COLUMN lng DOUBLE PRECISION;
+
+ALTER TABLE places ADD CONSTRAINT places_coordinates_check CHECK (
+  (lat IS NULL) = (lng IS NULL)
+  AND lat BETWEEN -90 AND 90
+  AND lng BETWEEN -180 AND 180
+);
+
+CREATE INDEX places_coordinates_idx ON places (lat, lng) WHERE deleted_at IS NULL;
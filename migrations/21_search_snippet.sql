@@ -0,0 +1,8 @@
+-- search snippets are HTML, so what users typed is escaped before
+-- ts_headline adds its <mark> tags. The parser reads the entities as such
+-- and never highlights inside them.
+CREATE OR REPLACE FUNCTION html_escape(TEXT)
+RETURNS TEXT AS $$
+  SELECT replace(replace(replace(replace(replace($1,
+    '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '"', '&quot;'), '''', '&#39;');
+$$ LANGUAGE sql IMMUTABLE PARALLEL SAFE STRICT;
@@ -0,0 +1,5 @@
+-- deleted accounts keep their email, which must not stop anyone from
+-- signing up with it again. The index keeps the name of the constraint it
+-- replaces, which is what the services look for.
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+CREATE UNIQUE INDEX users_email_key ON users (LOWER(email)) WHERE deleted_at IS NULL;
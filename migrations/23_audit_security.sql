@@ -0,0 +1,6 @@
+-- lockouts and role policies are audited like everything else, policies
+-- get an id for the log to point at
+ALTER TYPE audit_entity ADD VALUE 'lockout';
+ALTER TYPE audit_entity ADD VALUE 'role_policy';
+
+ALTER TABLE role_policies ADD COLUMN id SERIAL UNIQUE;
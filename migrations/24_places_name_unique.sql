@@ -0,0 +1,5 @@
+-- deleted places keep their name, which must not stop a new place from
+-- taking it. The index keeps the name of the constraint it replaces, which
+-- is what the services look for.
+ALTER TABLE places DROP CONSTRAINT places_name_key;
+CREATE UNIQUE INDEX places_name_key ON places (name) WHERE deleted_at IS NULL;
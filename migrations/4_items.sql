@@ -0,0 +1,15 @@
+CREATE TABLE items (
+  id SERIAL PRIMARY KEY,
+  sku VARCHAR(64) NOT NULL UNIQUE,
+  name VARCHAR(255) NOT NULL,
+  description VARCHAR(255),
+  unit VARCHAR(32) NOT NULL DEFAULT 'un',
+  image VARCHAR(255),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER update_me_daddy
+BEFORE UPDATE ON items
+FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,41 @@
+CREATE TYPE movement_kind AS ENUM ('receipt', 'issue', 'adjustment', 'transfer');
+
+-- append-only ledger, every change in stock is one row here
+CREATE TABLE stock_movements (
+  id SERIAL PRIMARY KEY,
+  item_id INTEGER NOT NULL REFERENCES items(id),
+  place_id INTEGER NOT NULL REFERENCES places(id),
+  kind movement_kind NOT NULL,
+  quantity INTEGER NOT NULL CHECK (quantity <> 0),
+  note VARCHAR(255),
+  user_id INTEGER REFERENCES users(id),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX stock_movements_item_place_idx ON stock_movements (item_id, place_id);
+
+CREATE OR REPLACE FUNCTION forbid_movement_change()
+RETURNS TRIGGER AS $$
+BEGIN
+  RAISE EXCEPTION 'stock movements are append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER stock_movements_append_only
+BEFORE UPDATE OR DELETE ON stock_movements
+FOR EACH ROW
+EXECUTE PROCEDURE forbid_movement_change();
+
+-- on hand balance per item and place, always the sum of the ledger
+CREATE TABLE stock_levels (
+  item_id INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+  place_id INTEGER NOT NULL REFERENCES places(id) ON DELETE CASCADE,
+  quantity INTEGER NOT NULL DEFAULT 0 CHECK (quantity >= 0),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (item_id, place_id)
+);
+
+CREATE TRIGGER update_me_daddy
+BEFORE UPDATE ON stock_levels
+FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,14 @@
+CREATE TABLE stock_transfers (
+  id SERIAL PRIMARY KEY,
+  item_id INTEGER NOT NULL REFERENCES items(id),
+  from_place_id INTEGER NOT NULL REFERENCES places(id),
+  to_place_id INTEGER NOT NULL REFERENCES places(id),
+  quantity INTEGER NOT NULL CHECK (quantity > 0),
+  note VARCHAR(255),
+  user_id INTEGER NOT NULL REFERENCES users(id),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  CHECK (from_place_id <> to_place_id)
+);
+
+-- both legs of a transfer point back to it
+ALTER TABLE stock_movements ADD COLUMN transfer_id INTEGER REFERENCES stock_transfers(id);
@@ -0,0 +1,34 @@
+CREATE TABLE loans (
+  id SERIAL PRIMARY KEY,
+  item_id INTEGER NOT NULL REFERENCES items(id),
+  place_id INTEGER NOT NULL REFERENCES places(id),
+  user_id INTEGER NOT NULL REFERENCES users(id),
+  quantity INTEGER NOT NULL CHECK (quantity > 0),
+  returned_quantity INTEGER NOT NULL DEFAULT 0,
+  due_at TIMESTAMPTZ NOT NULL,
+  note VARCHAR(255),
+  returned_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  CONSTRAINT loans_returned_quantity_check CHECK (returned_quantity BETWEEN 0 AND quantity)
+);
+
+CREATE INDEX loans_user_id_idx ON loans (user_id);
+CREATE INDEX loans_item_id_idx ON loans (item_id);
+
+CREATE TRIGGER update_me_daddy
+BEFORE UPDATE ON loans
+FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
+
+CREATE TABLE loan_returns (
+  id SERIAL PRIMARY KEY,
+  loan_id INTEGER NOT NULL REFERENCES loans(id),
+  quantity INTEGER NOT NULL CHECK (quantity > 0),
+  condition VARCHAR(255),
+  user_id INTEGER NOT NULL REFERENCES users(id),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- checkouts and returns show up in the ledger as issues and receipts
+ALTER TABLE stock_movements ADD COLUMN loan_id INTEGER REFERENCES loans(id);
@@ -0,0 +1,34 @@
+CREATE TYPE requisition_status AS ENUM (
+  'draft', 'submitted', 'approved', 'rejected', 'fulfilled', 'cancelled'
+);
+CREATE TYPE requisition_line_status AS ENUM ('pending', 'approved', 'rejected');
+
+CREATE TABLE requisitions (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users(id),
+  status requisition_status NOT NULL DEFAULT 'draft',
+  note VARCHAR(255),
+  place_id INTEGER REFERENCES places(id),
+  approver_id INTEGER REFERENCES users(id),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX requisitions_user_id_idx ON requisitions (user_id);
+
+CREATE TRIGGER update_me_daddy
+BEFORE UPDATE ON requisitions
+FOR EACH ROW
+EXECUTE PROCEDURE trigger_set_timestamp();
+
+CREATE TABLE requisition_lines (
+  id SERIAL PRIMARY KEY,
+  requisition_id INTEGER NOT NULL REFERENCES requisitions(id) ON DELETE CASCADE,
+  item_id INTEGER NOT NULL REFERENCES items(id),
+  quantity INTEGER NOT NULL CHECK (quantity > 0),
+  status requisition_line_status NOT NULL DEFAULT 'pending',
+  UNIQUE (requisition_id, item_id)
+);
+
+-- fulfilling a requisition issues its approved lines
+ALTER TABLE stock_movements ADD COLUMN requisition_id INTEGER REFERENCES requisitions(id);
@@ -1,38 +1,92 @@
-use crate::{validation::CustomError, AppState, Result};
+use std::marker::PhantomData;
+
+use crate::{
+    client::ClientInfo,
+    config::Config,
+    models::{account_model::TokenPurpose, user_model::Role},
+    services::{api_key_service, session_service},
+    validation::CustomError,
+    AppState, Result,
+};
 use axum::{
     async_trait,
     extract::{FromRequestParts, TypedHeader},
     headers::{authorization::Bearer, Authorization},
-    http::request::Parts,
+    http::{header::AUTHORIZATION, request::Parts},
     Extension, RequestPartsExt,
 };
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: i32,
+    pub role: Role,
+    pub verified: bool,
+    /// Set while the role requires two-factor authentication and the user
+    /// hasn't set it up yet.
+    pub two_factor_missing: bool,
+    /// The session this token was issued for, revoking it invalidates the token.
+    /// `None` when authenticated with an API key.
+    pub sid: Option<i32>,
+    /// What an API key was limited to, `None` grants everything the role does.
+    #[serde(skip)]
+    pub scopes: Option<Vec<Permission>>,
     exp: usize,
 }
 
 impl Claims {
-    pub fn new(sub: i32) -> Self {
+    pub fn new(
+        sub: i32,
+        role: Role,
+        verified: bool,
+        two_factor_missing: bool,
+        sid: i32,
+        ttl: Duration,
+    ) -> Self {
         Self {
             sub,
-            exp: Utc::now().timestamp() as usize + Duration::weeks(2).num_seconds() as usize,
+            role,
+            verified,
+            two_factor_missing,
+            sid: Some(sid),
+            scopes: None,
+            exp: (Utc::now() + ttl).timestamp() as usize,
         }
     }
 
     pub fn to_jwt(&self, ctx: &Extension<AppState>) -> Result<String> {
-        let key = EncodingKey::from_secret(ctx.config.hmac_key.as_bytes());
-        let token = encode(&Header::default(), self, &key).map_err(|e| {
-            tracing::error!("Could not encode JWT: {}", e);
-            CustomError::Anyhow(e.into())
+        let token = ctx.keys.sign(self).map_err(|e| {
+            tracing::error!("Could not encode JWT: {:?}", e);
+            CustomError::Anyhow(e)
         })?;
 
         Ok(token)
     }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        self.role.can(permission)
+            && self
+                .scopes
+                .as_ref()
+                .is_none_or(|scopes| scopes.contains(&permission))
+    }
+
+    /// The session of an interactive login, for routes API keys may not use.
+    pub fn session(&self) -> Result<i32> {
+        self.sid.ok_or(CustomError::Forbidden)
+    }
+
+    /// Lets users act on their own account, and on anyone else's only when
+    /// their role grants `permission`.
+    pub fn ensure_self_or(&self, user_id: i32, permission: Permission) -> Result<()> {
+        if self.sub == user_id || self.can(permission) {
+            Ok(())
+        } else {
+            Err(CustomError::Forbidden)
+        }
+    }
 }
 
 #[async_trait]
@@ -43,10 +97,10 @@ where
     type Rejection = CustomError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let TypedHeader(Authorization(bearer)) = parts
-            .extract::<TypedHeader<Authorization<Bearer>>>()
-            .await
-            .map_err(|_| CustomError::Unauthorized)?;
+        // already checked by another extractor of the same request
+        if let Some(claims) = parts.extensions.get::<Claims>() {
+            return Ok(claims.clone());
+        }
 
         let ctx: Extension<AppState> =
             Extension::from_request_parts(parts, state)
@@ -56,11 +110,226 @@ where
                     CustomError::Anyhow(e.into())
                 })?;
 
-        let key = DecodingKey::from_secret(ctx.config.hmac_key.as_bytes());
+        let api_key = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("ApiKey "));
+
+        if let Some(api_key) = api_key {
+            let owner = api_key_service::authenticate(&ctx.db, api_key.trim())
+                .await?
+                .ok_or(CustomError::Unauthorized)?;
+
+            let claims = Claims {
+                sub: owner.user_id,
+                role: owner.role,
+                verified: owner.verified,
+                two_factor_missing: owner.two_factor_missing,
+                sid: None,
+                scopes: Some(owner.scopes),
+                exp: 0,
+            };
+            parts.extensions.insert(claims.clone());
 
-        let token_data = decode::<Claims>(bearer.token(), &key, &Validation::default())
+            return Ok(claims);
+        }
+
+        let TypedHeader(Authorization(bearer)) = parts
+            .extract::<TypedHeader<Authorization<Bearer>>>()
+            .await
             .map_err(|_| CustomError::Unauthorized)?;
 
-        Ok(token_data.claims)
+        let claims = ctx
+            .keys
+            .verify::<Claims>(bearer.token())
+            .ok_or(CustomError::Unauthorized)?;
+
+        let Some(sid) = claims.sid else {
+            return Err(CustomError::Unauthorized);
+        };
+        if !session_service::is_session_active(&ctx.db, sid).await? {
+            return Err(CustomError::Unauthorized);
+        }
+        parts.extensions.insert(claims.clone());
+
+        Ok(claims)
+    }
+}
+
+/// Who is behind a mutation, as recorded in the audit log. The user is `None`
+/// for anonymous requests such as signing up.
+#[derive(Debug, Clone, Default)]
+pub struct Actor {
+    pub user_id: Option<i32>,
+    pub request_id: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl Actor {
+    pub fn new(user_id: Option<i32>, client: &ClientInfo) -> Self {
+        Self {
+            user_id,
+            request_id: client.request_id.clone(),
+            ip: client.ip.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Actor
+where
+    S: Send + Sync,
+{
+    type Rejection = CustomError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Ok(client) = ClientInfo::from_request_parts(parts, state).await;
+        let user_id = Claims::from_request_parts(parts, state)
+            .await
+            .ok()
+            .map(|claims| claims.sub);
+
+        Ok(Self::new(user_id, &client))
+    }
+}
+
+/// Claims of the single-use links we send by mail, `jti` points at the
+/// `action_tokens` row that is burnt when the link is used.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionClaims {
+    pub sub: i32,
+    pub jti: i32,
+    pub purpose: TokenPurpose,
+    exp: usize,
+}
+
+impl ActionClaims {
+    pub fn new(sub: i32, jti: i32, purpose: TokenPurpose, expires_at: DateTime<Utc>) -> Self {
+        Self {
+            sub,
+            jti,
+            purpose,
+            exp: expires_at.timestamp() as usize,
+        }
+    }
+
+    pub fn to_jwt(&self, config: &Config) -> Result<String> {
+        let key = EncodingKey::from_secret(config.hmac_key.as_bytes());
+        let token = encode(&Header::default(), self, &key).map_err(|e| {
+            tracing::error!("Could not encode JWT: {}", e);
+            CustomError::Anyhow(e.into())
+        })?;
+
+        Ok(token)
+    }
+
+    /// Checks the signature and expiry of `token` and that it was issued for `purpose`.
+    pub fn from_jwt(token: &str, purpose: TokenPurpose, config: &Config) -> Option<Self> {
+        let key = DecodingKey::from_secret(config.hmac_key.as_bytes());
+
+        decode::<ActionClaims>(token, &key, &Validation::default())
+            .ok()
+            .map(|data| data.claims)
+            .filter(|claims| claims.purpose == purpose)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "permission", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    ManageUsers,
+    ManagePlaces,
+    ManageItems,
+    MoveStock,
+    RequestMaterials,
+    ApproveRequisitions,
+    ViewAudit,
+}
+
+impl sqlx::postgres::PgHasArrayType for Permission {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_permission")
+    }
+}
+
+impl Role {
+    pub fn can(&self, permission: Permission) -> bool {
+        use Permission::*;
+
+        match self {
+            Role::Admin => true,
+            Role::Storekeeper => matches!(
+                permission,
+                ManagePlaces | ManageItems | MoveStock | RequestMaterials | ApproveRequisitions
+            ),
+            Role::Requester => matches!(permission, RequestMaterials),
+            Role::Auditor => matches!(permission, ViewAudit),
+        }
+    }
+}
+
+pub trait Policy {
+    const PERMISSION: Permission;
+}
+
+macro_rules! policies {
+    ($($permission:ident),* $(,)?) => {
+        /// Marker types naming a [`Permission`](super::Permission) at the type
+        /// level, so a route can ask for it with `Authorized<can::ManagePlaces>`.
+        pub mod can {
+            $(
+                pub struct $permission;
+
+                impl super::Policy for $permission {
+                    const PERMISSION: super::Permission = super::Permission::$permission;
+                }
+            )*
+        }
+    };
+}
+
+policies!(
+    ManageUsers,
+    ManagePlaces,
+    ManageItems,
+    MoveStock,
+    RequestMaterials,
+    ApproveRequisitions,
+    ViewAudit,
+);
+
+/// Claims of a user whose role grants the permission named by `P`.
+pub struct Authorized<P> {
+    pub claims: Claims,
+    policy: PhantomData<P>,
+}
+
+#[async_trait]
+impl<S, P> FromRequestParts<S> for Authorized<P>
+where
+    S: Send + Sync,
+    P: Policy,
+{
+    type Rejection = CustomError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let claims = Claims::from_request_parts(parts, state).await?;
+
+        if !claims.verified {
+            return Err(CustomError::EmailNotVerified);
+        }
+        if claims.two_factor_missing {
+            return Err(CustomError::TwoFactorRequired);
+        }
+        if !claims.can(P::PERMISSION) {
+            return Err(CustomError::Forbidden);
+        }
+
+        Ok(Self {
+            claims,
+            policy: PhantomData,
+        })
     }
 }
@@ -0,0 +1,36 @@
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use sqlx::postgres::PgPoolOptions;
+use usguri_almoxarifado::promote_admin;
+
+/// Makes an existing user an admin, for installations that have none such as
+/// those set up before roles existed.
+#[derive(Parser, Debug)]
+struct Args {
+    /// The connection URL for the Postgres database this application should use.
+    #[clap(long, env)]
+    database_url: String,
+
+    /// The email address of the user to promote.
+    email: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenvy::dotenv().ok();
+    let args = Args::parse();
+
+    let db = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&args.database_url)
+        .await
+        .context("could not connect to database_url")?;
+
+    if !promote_admin(&db, &args.email).await? {
+        bail!("there is no user with the email address {}", args.email);
+    }
+
+    println!("{} is now an admin", args.email);
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts, Request},
+};
+
+/// Who is on the other end of a request, as far as we can tell.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// The `x-request-id` we set on every request, to tie log lines and audit
+    /// entries together.
+    pub request_id: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(255).collect());
+
+        let request_id = parts
+            .headers
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(64).collect());
+
+        Ok(Self {
+            ip,
+            user_agent,
+            request_id,
+        })
+    }
+}
+
+/// Drops the `x-request-id` a client sent, so the one the audit log and error
+/// bodies carry is always ours and can't be made to match another request.
+/// Goes outside of the layer that sets it.
+pub async fn drop_request_id<B>(mut req: Request<B>) -> Request<B> {
+    if let Some(sent) = req.headers_mut().remove("x-request-id") {
+        tracing::debug!("Ignoring x-request-id sent by the client: {:?}", sent);
+    }
+
+    req
+}
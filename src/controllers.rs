@@ -1,3 +1,12 @@
+pub mod api_key_controller;
+pub mod audit_controller;
+pub mod image_controller;
+pub mod item_controller;
+pub mod loan_controller;
 pub mod place_controller;
 pub mod profile_controller;
+pub mod requisition_controller;
+pub mod search_controller;
+pub mod stock_controller;
 pub mod user_controller;
+pub mod well_known_controller;
@@ -0,0 +1,77 @@
+use crate::{
+    authorization::{can, Actor, Authorized, Claims, Permission},
+    models::api_key_model::{ApiKeyBody, ApiKeyEntity, CreateApiKeyDTO},
+    services::api_key_service,
+    validation::{CustomError, Path, ValidatedRequest},
+    AppState, Result,
+};
+use axum::{
+    routing::{get, post},
+    Extension, Json, Router,
+};
+
+async fn get_my_api_keys(
+    state: Extension<AppState>,
+    claims: Claims,
+) -> Result<Json<Vec<ApiKeyEntity>>> {
+    let api_keys = api_key_service::get_api_keys(&state.db, claims.sub).await?;
+
+    Ok(Json(api_keys))
+}
+
+async fn get_user_api_keys(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageUsers>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<ApiKeyEntity>>> {
+    let api_keys = api_key_service::get_api_keys(&state.db, id).await?;
+
+    Ok(Json(api_keys))
+}
+
+/// Keys can only be made from an interactive login, never by another key.
+async fn create_api_key(
+    state: Extension<AppState>,
+    claims: Claims,
+    actor: Actor,
+    ValidatedRequest(data): ValidatedRequest<CreateApiKeyDTO>,
+) -> Result<Json<ApiKeyBody>> {
+    claims.session()?;
+    let api_key =
+        api_key_service::create_api_key(&state.db, claims.sub, claims.role, data, &actor).await?;
+
+    Ok(Json(api_key))
+}
+
+async fn revoke_api_key(
+    state: Extension<AppState>,
+    claims: Claims,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<ApiKeyEntity>> {
+    claims.session()?;
+    let api_key = api_key_service::get_api_key(&state.db, id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+    claims.ensure_self_or(api_key.user_id, Permission::ManageUsers)?;
+
+    let api_key = api_key_service::revoke_api_key(&state.db, id, &actor).await?;
+
+    match api_key {
+        Some(api_key) => Ok(Json(api_key)),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+fn real_route() -> Router {
+    Router::new()
+        .route("/", get(get_my_api_keys))
+        .route("/me", get(get_my_api_keys))
+        .route("/user/:id", get(get_user_api_keys))
+        .route("/create", post(create_api_key))
+        .route("/:id/revoke", post(revoke_api_key))
+}
+
+pub fn route() -> Router {
+    Router::new().nest("/api-key", real_route())
+}
@@ -0,0 +1,42 @@
+use crate::{
+    authorization::{can, Authorized},
+    models::audit_model::{AuditEntity, AuditQuery},
+    services::audit_service,
+    validation::{CustomError, Path, Query},
+    AppState, Result,
+};
+use axum::{routing::get, Extension, Json, Router};
+
+async fn get_entries(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ViewAudit>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntity>>> {
+    let entries = audit_service::get_entries(&state.db, query).await?;
+
+    Ok(Json(entries))
+}
+
+async fn get_entry(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ViewAudit>,
+    Path(id): Path<i32>,
+) -> Result<Json<AuditEntity>> {
+    let entry = audit_service::get_entry(&state.db, id).await?;
+
+    match entry {
+        Some(entry) => Ok(Json(entry)),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+fn real_route() -> Router {
+    Router::new()
+        .route("/", get(get_entries))
+        .route("/all", get(get_entries))
+        .route("/:id", get(get_entry))
+}
+
+pub fn route() -> Router {
+    Router::new().nest("/audit", real_route())
+}
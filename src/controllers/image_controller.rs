@@ -0,0 +1,129 @@
+use crate::{
+    etag,
+    services::image_service,
+    validation::{field_error, CustomError, Path},
+    AppState, Result,
+};
+use anyhow::Context;
+use axum::{
+    async_trait,
+    extract::{multipart::MultipartRejection, FromRequest, Multipart},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+        HeaderMap, Request, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+
+/// What is served under a name never changes, so it can be kept for good.
+const CACHE_FOREVER: &str = "public, max-age=31536000, immutable";
+
+/// The `image` field of a `multipart/form-data` upload, no larger than
+/// `max_upload_bytes`. Routes taking it should lift the default body limit,
+/// this enforces its own.
+pub struct ImageUpload {
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+#[async_trait]
+impl<S, B> FromRequest<S, B> for ImageUpload
+where
+    Multipart: FromRequest<S, B, Rejection = MultipartRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = CustomError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let limit = req
+            .extensions()
+            .get::<AppState>()
+            .context("AppState extension is missing")?
+            .config
+            .max_upload_bytes;
+        let mut multipart = Multipart::from_request(req, state).await?;
+
+        // other fields are refused rather than skipped, which would mean
+        // reading them whatever their size
+        let Some(mut field) = multipart.next_field().await? else {
+            return Err(field_error("image", "is required"));
+        };
+        if field.name() != Some("image") {
+            return Err(field_error("image", "must be the only field"));
+        }
+
+        let content_type = field.content_type().unwrap_or_default().to_string();
+        let mut data = vec![];
+        while let Some(chunk) = field.chunk().await? {
+            if data.len() + chunk.len() > limit {
+                return Err(CustomError::PayloadTooLarge { limit });
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        Ok(Self { content_type, data })
+    }
+}
+
+async fn get_image(
+    state: Extension<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    serve(&state, &name, false, &headers).await
+}
+
+async fn get_thumbnail(
+    state: Extension<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    serve(&state, &name, true, &headers).await
+}
+
+async fn serve(
+    state: &AppState,
+    name: &str,
+    thumbnail: bool,
+    headers: &HeaderMap,
+) -> Result<Response> {
+    let etag = format!("\"{}\"", name);
+    let cached = headers
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| etag::matches(value, &etag, true));
+    if cached && image_service::content_type(name).is_some() {
+        return Ok((
+            StatusCode::NOT_MODIFIED,
+            [(CACHE_CONTROL, CACHE_FOREVER.to_string()), (ETAG, etag)],
+        )
+            .into_response());
+    }
+
+    let (data, content_type) = image_service::get_image(&*state.storage, name, thumbnail)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, content_type.to_string()),
+            (CACHE_CONTROL, CACHE_FOREVER.to_string()),
+            (ETAG, etag),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+fn real_route() -> Router {
+    Router::new()
+        .route("/:name", get(get_image))
+        .route("/thumbnails/:name", get(get_thumbnail))
+}
+
+pub fn route() -> Router {
+    Router::new().nest("/images", real_route())
+}
@@ -0,0 +1,169 @@
+use crate::{
+    authorization::{can, Actor, Authorized},
+    controllers::image_controller::ImageUpload,
+    etag::{self, IfMatch, IfNoneMatch},
+    models::{
+        item_model::{CreateItemDTO, ItemEntity, UpdateItemDTO},
+        loan_model::LoanEntity,
+        stock_model::StockLevelEntity,
+    },
+    services::{image_service, item_service, loan_service, stock_service},
+    validation::{CustomError, Path, ValidatedRequest},
+    AppState, Result,
+};
+use axum::{
+    extract::DefaultBodyLimit,
+    http::StatusCode,
+    response::Response,
+    routing::{delete, get, patch, post},
+    Extension, Json, Router,
+};
+
+async fn get_all(state: Extension<AppState>) -> Result<Json<Vec<ItemEntity>>> {
+    let items = item_service::get_all_items(&state.db).await?;
+
+    Ok(Json(items))
+}
+
+async fn get_item(
+    state: Extension<AppState>,
+    Path(id): Path<i32>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response> {
+    let item = item_service::get_item(&state.db, id).await?;
+
+    match item {
+        Some(item) => if_none_match.respond(item),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn get_item_stock(
+    state: Extension<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<StockLevelEntity>>> {
+    item_service::get_item(&state.db, id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+    let stock = stock_service::get_item_stock(&state.db, id).await?;
+
+    Ok(Json(stock))
+}
+
+async fn get_item_loans(
+    state: Extension<AppState>,
+    _auth: Authorized<can::MoveStock>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<LoanEntity>>> {
+    item_service::get_item(&state.db, id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+    let loans = loan_service::get_loans_by_item(&state.db, id).await?;
+
+    Ok(Json(loans))
+}
+
+async fn create_item(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageItems>,
+    actor: Actor,
+    ValidatedRequest(data): ValidatedRequest<CreateItemDTO>,
+) -> Result<Json<ItemEntity>> {
+    let item = item_service::create_item(&state.db, data, &actor).await?;
+
+    Ok(Json(item))
+}
+
+async fn update_item(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageItems>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+    ValidatedRequest(data): ValidatedRequest<UpdateItemDTO>,
+) -> Result<Response> {
+    let item = item_service::update_item(&state.db, id, data, &if_match, &actor).await?;
+
+    match item {
+        Some(item) => etag::tagged(item),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn upload_image(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageItems>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+    upload: ImageUpload,
+) -> Result<Response> {
+    let storage = &*state.storage;
+    let name = image_service::store_image(storage, &upload.content_type, upload.data).await?;
+    let item = item_service::set_image(
+        &state.db,
+        storage,
+        id,
+        Some(name.clone()),
+        &if_match,
+        &actor,
+    )
+    .await;
+    if !matches!(item, Ok(Some(_))) {
+        image_service::release_image(storage, Some(name)).await;
+    }
+
+    match item? {
+        Some(item) => etag::tagged(item),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn delete_image(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageItems>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+) -> Result<Response> {
+    let item =
+        item_service::set_image(&state.db, &*state.storage, id, None, &if_match, &actor).await?;
+
+    match item {
+        Some(item) => etag::tagged(item),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn delete_item(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageItems>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+) -> Result<StatusCode> {
+    item_service::delete_item(&state.db, &*state.storage, id, &if_match, &actor).await?;
+    Ok(StatusCode::OK)
+}
+
+fn real_route() -> Router {
+    Router::new()
+        .route("/", get(get_all))
+        .route("/all", get(get_all))
+        .route("/:id", get(get_item))
+        .route("/:id/stock", get(get_item_stock))
+        .route("/:id/loans", get(get_item_loans))
+        .route(
+            "/:id/image",
+            post(upload_image)
+                .delete(delete_image)
+                .layer(DefaultBodyLimit::disable()),
+        )
+        .route("/create", post(create_item))
+        .route("/update/:id", patch(update_item))
+        .route("/delete/:id", delete(delete_item))
+}
+
+pub fn route() -> Router {
+    Router::new().nest("/item", real_route())
+}
@@ -0,0 +1,108 @@
+use crate::{
+    authorization::{can, Actor, Authorized, Claims, Permission},
+    models::loan_model::{CheckoutDTO, LoanEntity, LoanReturnEntity, ReturnLoanDTO},
+    services::loan_service,
+    validation::{CustomError, Path, ValidatedRequest},
+    AppState, Result,
+};
+use axum::{
+    routing::{get, post},
+    Extension, Json, Router,
+};
+
+async fn get_loan(
+    state: Extension<AppState>,
+    claims: Claims,
+    Path(id): Path<i32>,
+) -> Result<Json<LoanEntity>> {
+    let loan = loan_service::get_loan(&state.db, id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+    claims.ensure_self_or(loan.user_id, Permission::MoveStock)?;
+
+    Ok(Json(loan))
+}
+
+async fn get_loan_returns(
+    state: Extension<AppState>,
+    claims: Claims,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<LoanReturnEntity>>> {
+    let loan = loan_service::get_loan(&state.db, id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+    claims.ensure_self_or(loan.user_id, Permission::MoveStock)?;
+    let returns = loan_service::get_loan_returns(&state.db, id).await?;
+
+    Ok(Json(returns))
+}
+
+async fn get_my_loans(state: Extension<AppState>, claims: Claims) -> Result<Json<Vec<LoanEntity>>> {
+    let loans = loan_service::get_open_loans_by_user(&state.db, claims.sub).await?;
+
+    Ok(Json(loans))
+}
+
+async fn get_user_loans(
+    state: Extension<AppState>,
+    claims: Claims,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<LoanEntity>>> {
+    claims.ensure_self_or(id, Permission::MoveStock)?;
+    let loans = loan_service::get_open_loans_by_user(&state.db, id).await?;
+
+    Ok(Json(loans))
+}
+
+async fn get_overdue(
+    state: Extension<AppState>,
+    _auth: Authorized<can::MoveStock>,
+) -> Result<Json<Vec<LoanEntity>>> {
+    let loans = loan_service::get_overdue_loans(&state.db).await?;
+
+    Ok(Json(loans))
+}
+
+async fn checkout(
+    state: Extension<AppState>,
+    auth: Authorized<can::RequestMaterials>,
+    actor: Actor,
+    ValidatedRequest(data): ValidatedRequest<CheckoutDTO>,
+) -> Result<Json<LoanEntity>> {
+    let loan = loan_service::checkout(&state.db, data, auth.claims.sub, &actor).await?;
+
+    Ok(Json(loan))
+}
+
+/// Borrowers return their own loans, staff anyone's.
+async fn return_loan(
+    state: Extension<AppState>,
+    claims: Claims,
+    actor: Actor,
+    Path(id): Path<i32>,
+    ValidatedRequest(data): ValidatedRequest<ReturnLoanDTO>,
+) -> Result<Json<LoanEntity>> {
+    let loan = loan_service::get_loan(&state.db, id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+    claims.ensure_self_or(loan.user_id, Permission::MoveStock)?;
+
+    let loan = loan_service::return_loan(&state.db, id, data, claims.sub, &actor).await?;
+
+    Ok(Json(loan))
+}
+
+fn real_route() -> Router {
+    Router::new()
+        .route("/me", get(get_my_loans))
+        .route("/overdue", get(get_overdue))
+        .route("/user/:id", get(get_user_loans))
+        .route("/checkout", post(checkout))
+        .route("/:id", get(get_loan))
+        .route("/:id/returns", get(get_loan_returns))
+        .route("/:id/return", post(return_loan))
+}
+
+pub fn route() -> Router {
+    Router::new().nest("/loan", real_route())
+}
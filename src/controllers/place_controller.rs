@@ -1,62 +1,253 @@
 use crate::{
-    models::place_model::{CreatePlaceDTO, PlaceEntity, UpdatePlaceDTO},
-    services::place_service,
-    validation::{CustomError, ValidatedRequest},
+    authorization::{can, Actor, Authorized},
+    controllers::image_controller::ImageUpload,
+    etag::{self, IfMatch, IfNoneMatch},
+    models::{
+        place_model::{
+            CreatePlaceDTO, NearbyPlace, NearbyQuery, PlaceCollection, PlaceEntity, PlaceNode,
+            PlaceQuery, UpdatePlaceDTO,
+        },
+        stock_model::StockTotal,
+    },
+    pagination::{ListQuery, Page},
+    services::{image_service, place_service, stock_service},
+    validation::{CustomError, Path, Query, ValidatedRequest},
     AppState, Result,
 };
 use axum::{
-    extract::Path,
-    http::StatusCode,
+    extract::DefaultBodyLimit,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
     routing::{delete, get, patch, post},
     Extension, Json, Router,
 };
+use validator::Validate;
 
-async fn get_all(state: Extension<AppState>) -> Result<Json<Vec<PlaceEntity>>> {
-    let places = place_service::get_all_places(&state.db).await?;
+async fn get_all(
+    state: Extension<AppState>,
+    auth: Option<Authorized<can::ManagePlaces>>,
+    query: ListQuery<PlaceQuery>,
+) -> Result<Json<Page<PlaceEntity>>> {
+    if query.filter.include_deleted && auth.is_none() {
+        return Err(CustomError::Forbidden);
+    }
+    let places = place_service::get_all_places(&state.db, query).await?;
 
     Ok(Json(places))
 }
 
-async fn get_place(state: Extension<AppState>, Path(id): Path<i32>) -> Result<Json<PlaceEntity>> {
+async fn get_nearby(
+    state: Extension<AppState>,
+    Query(query): Query<NearbyQuery>,
+) -> Result<Json<Vec<NearbyPlace>>> {
+    query.validate()?;
+    let places = place_service::get_nearby(&state.db, query).await?;
+
+    Ok(Json(places))
+}
+
+async fn get_geojson(state: Extension<AppState>) -> Result<impl IntoResponse> {
+    let places = place_service::get_located_places(&state.db).await?;
+
+    Ok((
+        [(CONTENT_TYPE, "application/geo+json")],
+        Json(PlaceCollection::new(places)),
+    ))
+}
+
+async fn get_place(
+    state: Extension<AppState>,
+    Path(id): Path<i32>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response> {
     let place = place_service::get_place(&state.db, id).await?;
 
     match place {
-        Some(place) => Ok(Json(place)),
+        Some(place) => if_none_match.respond(place),
         None => Err(CustomError::NotFound),
     }
 }
 
+async fn get_place_stock(
+    state: Extension<AppState>,
+    Path(id): Path<i32>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response> {
+    place_service::get_place(&state.db, id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+    let stock = stock_service::get_place_stock(&state.db, id).await?;
+
+    if_none_match.respond(stock)
+}
+
+/// The balance of one item right at the place, tagged so that adjustments
+/// made from it can be sent with `If-Match`.
+async fn get_place_item_stock(
+    state: Extension<AppState>,
+    Path((id, item_id)): Path<(i32, i32)>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response> {
+    let level = stock_service::get_level(&state.db, item_id, id).await?;
+
+    match level {
+        Some(level) => if_none_match.respond(level),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn get_place_totals(
+    state: Extension<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<StockTotal>>> {
+    place_service::get_place(&state.db, id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+    let totals = stock_service::get_place_totals(&state.db, id).await?;
+
+    Ok(Json(totals))
+}
+
+async fn get_subtree(state: Extension<AppState>, Path(id): Path<i32>) -> Result<Json<PlaceNode>> {
+    let tree = place_service::get_subtree(&state.db, id).await?;
+
+    match tree {
+        Some(tree) => Ok(Json(tree)),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn get_path(
+    state: Extension<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<PlaceEntity>>> {
+    let path = place_service::get_path(&state.db, id).await?;
+
+    if path.is_empty() {
+        return Err(CustomError::NotFound);
+    }
+    Ok(Json(path))
+}
+
 async fn create_place(
     state: Extension<AppState>,
+    _auth: Authorized<can::ManagePlaces>,
+    actor: Actor,
     ValidatedRequest(data): ValidatedRequest<CreatePlaceDTO>,
 ) -> Result<Json<PlaceEntity>> {
-    let place = place_service::create_place(&state.db, data).await?;
+    let place = place_service::create_place(&state.db, data, &actor).await?;
 
     Ok(Json(place))
 }
 
 async fn update_place(
     state: Extension<AppState>,
+    _auth: Authorized<can::ManagePlaces>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
     ValidatedRequest(data): ValidatedRequest<UpdatePlaceDTO>,
-) -> Result<Json<PlaceEntity>> {
-    let place = place_service::update_place(&state.db, data).await?;
+) -> Result<Response> {
+    let place = place_service::update_place(&state.db, id, data, &if_match, &actor).await?;
 
-    Ok(Json(place))
+    match place {
+        Some(place) => etag::tagged(place),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn upload_image(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManagePlaces>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+    upload: ImageUpload,
+) -> Result<Response> {
+    let storage = &*state.storage;
+    let name = image_service::store_image(storage, &upload.content_type, upload.data).await?;
+    let place = place_service::set_image(
+        &state.db,
+        storage,
+        id,
+        Some(name.clone()),
+        &if_match,
+        &actor,
+    )
+    .await;
+    if !matches!(place, Ok(Some(_))) {
+        image_service::release_image(storage, Some(name)).await;
+    }
+
+    match place? {
+        Some(place) => etag::tagged(place),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn delete_image(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManagePlaces>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+) -> Result<Response> {
+    let place =
+        place_service::set_image(&state.db, &*state.storage, id, None, &if_match, &actor).await?;
+
+    match place {
+        Some(place) => etag::tagged(place),
+        None => Err(CustomError::NotFound),
+    }
 }
 
-async fn delete_place(state: Extension<AppState>, Path(id): Path<i32>) -> Result<StatusCode> {
-    place_service::delete_place(&state.db, id).await?;
+async fn delete_place(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManagePlaces>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+) -> Result<StatusCode> {
+    place_service::delete_place(&state.db, id, &if_match, &actor).await?;
     Ok(StatusCode::OK)
 }
 
+async fn restore_place(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManagePlaces>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<PlaceEntity>> {
+    let place = place_service::restore_place(&state.db, id, &actor).await?;
+
+    match place {
+        Some(place) => Ok(Json(place)),
+        None => Err(CustomError::NotFound),
+    }
+}
+
 fn real_route() -> Router {
     Router::new()
         .route("/", get(get_all))
         .route("/all", get(get_all))
+        .route("/nearby", get(get_nearby))
+        .route("/geojson", get(get_geojson))
         .route("/:id", get(get_place))
+        .route("/:id/stock", get(get_place_stock))
+        .route("/:id/stock/totals", get(get_place_totals))
+        .route("/:id/stock/:item_id", get(get_place_item_stock))
+        .route("/:id/tree", get(get_subtree))
+        .route("/:id/path", get(get_path))
+        .route(
+            "/:id/image",
+            post(upload_image)
+                .delete(delete_image)
+                .layer(DefaultBodyLimit::disable()),
+        )
         .route("/create", post(create_place))
-        .route("update/:id", patch(update_place))
+        .route("/update/:id", patch(update_place))
         .route("/delete/:id", delete(delete_place))
+        .route("/restore/:id", post(restore_place))
 }
 
 pub fn route() -> Router {
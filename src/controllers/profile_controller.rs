@@ -1,20 +1,36 @@
-use axum::{extract::Path, routing::get, Extension, Json, Router};
+use axum::{response::Response, routing::get, Extension, Json, Router};
 
 use crate::Result;
 use crate::{
-    models::profile_model::ProfileEntity, services::profile_service, validation::CustomError,
+    authorization::{can, Authorized},
+    etag::IfNoneMatch,
+    models::profile_model::{ProfileEntity, ProfileQuery},
+    pagination::{ListQuery, Page},
+    services::profile_service,
+    validation::{CustomError, Path},
     AppState,
 };
 
-async fn get_all(state: Extension<AppState>) -> Result<Json<Vec<ProfileEntity>>> {
-    let users = profile_service::get_all_users(&state.db).await?;
+async fn get_all(
+    state: Extension<AppState>,
+    auth: Option<Authorized<can::ManageUsers>>,
+    query: ListQuery<ProfileQuery>,
+) -> Result<Json<Page<ProfileEntity>>> {
+    if query.filter.include_deleted && auth.is_none() {
+        return Err(CustomError::Forbidden);
+    }
+    let users = profile_service::get_all_users(query, &state.db).await?;
     Ok(Json(users))
 }
 
-async fn get_user(state: Extension<AppState>, Path(id): Path<i32>) -> Result<Json<ProfileEntity>> {
+async fn get_user(
+    state: Extension<AppState>,
+    Path(id): Path<i32>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response> {
     let user = profile_service::get_user(id, &state.db).await?;
     match user {
-        Some(user) => Ok(Json(user)),
+        Some(user) => if_none_match.respond(user),
         None => Err(CustomError::NotFound),
     }
 }
@@ -0,0 +1,150 @@
+use crate::{
+    authorization::{can, Actor, Authorized, Claims},
+    models::requisition_model::{
+        CreateRequisitionDTO, FulfilRequisitionDTO, RequisitionBody, RequisitionEntity,
+        RequisitionLineStatus, RequisitionQuery,
+    },
+    services::requisition_service,
+    validation::{CustomError, Path, Query, ValidatedRequest},
+    AppState, Result,
+};
+use axum::{
+    routing::{get, post},
+    Extension, Json, Router,
+};
+
+async fn get_all(
+    state: Extension<AppState>,
+    Query(query): Query<RequisitionQuery>,
+) -> Result<Json<Vec<RequisitionEntity>>> {
+    let requisitions = requisition_service::get_requisitions(&state.db, query).await?;
+
+    Ok(Json(requisitions))
+}
+
+async fn get_mine(
+    state: Extension<AppState>,
+    claims: Claims,
+) -> Result<Json<Vec<RequisitionEntity>>> {
+    let requisitions = requisition_service::get_requisitions_by_user(&state.db, claims.sub).await?;
+
+    Ok(Json(requisitions))
+}
+
+async fn get_requisition(
+    state: Extension<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<RequisitionBody>> {
+    let requisition = requisition_service::get_requisition(&state.db, id).await?;
+
+    match requisition {
+        Some(requisition) => Ok(Json(requisition)),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn create_requisition(
+    state: Extension<AppState>,
+    auth: Authorized<can::RequestMaterials>,
+    actor: Actor,
+    ValidatedRequest(data): ValidatedRequest<CreateRequisitionDTO>,
+) -> Result<Json<RequisitionBody>> {
+    let requisition =
+        requisition_service::create_requisition(&state.db, data, auth.claims.sub, &actor).await?;
+
+    Ok(Json(requisition))
+}
+
+async fn submit_requisition(
+    state: Extension<AppState>,
+    auth: Authorized<can::RequestMaterials>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<RequisitionEntity>> {
+    let requisition =
+        requisition_service::submit_requisition(&state.db, id, auth.claims.sub, &actor).await?;
+
+    Ok(Json(requisition))
+}
+
+async fn cancel_requisition(
+    state: Extension<AppState>,
+    auth: Authorized<can::RequestMaterials>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<RequisitionEntity>> {
+    let requisition =
+        requisition_service::cancel_requisition(&state.db, id, auth.claims.sub, &actor).await?;
+
+    Ok(Json(requisition))
+}
+
+async fn approve_line(
+    state: Extension<AppState>,
+    auth: Authorized<can::ApproveRequisitions>,
+    actor: Actor,
+    Path((id, line_id)): Path<(i32, i32)>,
+) -> Result<Json<RequisitionBody>> {
+    let requisition = requisition_service::decide_line(
+        &state.db,
+        id,
+        line_id,
+        RequisitionLineStatus::Approved,
+        auth.claims.sub,
+        &actor,
+    )
+    .await?;
+
+    Ok(Json(requisition))
+}
+
+async fn reject_line(
+    state: Extension<AppState>,
+    auth: Authorized<can::ApproveRequisitions>,
+    actor: Actor,
+    Path((id, line_id)): Path<(i32, i32)>,
+) -> Result<Json<RequisitionBody>> {
+    let requisition = requisition_service::decide_line(
+        &state.db,
+        id,
+        line_id,
+        RequisitionLineStatus::Rejected,
+        auth.claims.sub,
+        &actor,
+    )
+    .await?;
+
+    Ok(Json(requisition))
+}
+
+async fn fulfil_requisition(
+    state: Extension<AppState>,
+    auth: Authorized<can::MoveStock>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    ValidatedRequest(data): ValidatedRequest<FulfilRequisitionDTO>,
+) -> Result<Json<RequisitionBody>> {
+    let requisition =
+        requisition_service::fulfil_requisition(&state.db, id, data, auth.claims.sub, &actor)
+            .await?;
+
+    Ok(Json(requisition))
+}
+
+fn real_route() -> Router {
+    Router::new()
+        .route("/", get(get_all))
+        .route("/all", get(get_all))
+        .route("/me", get(get_mine))
+        .route("/create", post(create_requisition))
+        .route("/:id", get(get_requisition))
+        .route("/:id/submit", post(submit_requisition))
+        .route("/:id/cancel", post(cancel_requisition))
+        .route("/:id/line/:line_id/approve", post(approve_line))
+        .route("/:id/line/:line_id/reject", post(reject_line))
+        .route("/:id/fulfil", post(fulfil_requisition))
+}
+
+pub fn route() -> Router {
+    Router::new().nest("/requisition", real_route())
+}
@@ -0,0 +1,24 @@
+use crate::{
+    models::search_model::{SearchHit, SearchQuery},
+    services::search_service,
+    validation::Query,
+    AppState, Result,
+};
+use axum::{routing::get, Extension, Json, Router};
+
+async fn search(
+    state: Extension<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchHit>>> {
+    let hits = search_service::search(&state.db, query).await?;
+
+    Ok(Json(hits))
+}
+
+fn real_route() -> Router {
+    Router::new().route("/", get(search))
+}
+
+pub fn route() -> Router {
+    Router::new().nest("/search", real_route())
+}
@@ -0,0 +1,71 @@
+use crate::{
+    authorization::{can, Actor, Authorized},
+    etag::IfMatch,
+    models::stock_model::{
+        CreateMovementDTO, CreateTransferDTO, MovementQuery, StockMovementEntity, TransferEntity,
+    },
+    services::stock_service,
+    validation::{CustomError, Path, Query, ValidatedRequest},
+    AppState, Result,
+};
+use axum::{
+    routing::{get, post},
+    Extension, Json, Router,
+};
+
+async fn get_movements(
+    state: Extension<AppState>,
+    Query(query): Query<MovementQuery>,
+) -> Result<Json<Vec<StockMovementEntity>>> {
+    let movements = stock_service::get_movements(&state.db, query).await?;
+
+    Ok(Json(movements))
+}
+
+async fn create_movement(
+    state: Extension<AppState>,
+    auth: Authorized<can::MoveStock>,
+    actor: Actor,
+    if_match: IfMatch,
+    ValidatedRequest(data): ValidatedRequest<CreateMovementDTO>,
+) -> Result<Json<StockMovementEntity>> {
+    let movement =
+        stock_service::create_movement(&state.db, data, if_match, auth.claims.sub, &actor).await?;
+
+    Ok(Json(movement))
+}
+
+async fn get_transfer(
+    state: Extension<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<TransferEntity>> {
+    let transfer = stock_service::get_transfer(&state.db, id).await?;
+
+    match transfer {
+        Some(transfer) => Ok(Json(transfer)),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn create_transfer(
+    state: Extension<AppState>,
+    auth: Authorized<can::MoveStock>,
+    actor: Actor,
+    ValidatedRequest(data): ValidatedRequest<CreateTransferDTO>,
+) -> Result<Json<TransferEntity>> {
+    let transfer = stock_service::create_transfer(&state.db, data, auth.claims.sub, &actor).await?;
+
+    Ok(Json(transfer))
+}
+
+fn real_route() -> Router {
+    Router::new()
+        .route("/movements", get(get_movements))
+        .route("/movement", post(create_movement))
+        .route("/transfer", post(create_transfer))
+        .route("/transfer/:id", get(get_transfer))
+}
+
+pub fn route() -> Router {
+    Router::new().nest("/stock", real_route())
+}
@@ -1,82 +1,575 @@
 use crate::{
-    authorization::Claims,
-    models::user_model::{CreateUserDTO, LoginUserDTO, UserBody, UserEntity},
-    services::profile_service,
-    validation::ValidatedRequest,
+    authorization::{can, Actor, Authorized, Claims, Permission},
+    client::ClientInfo,
+    etag::{self, IfMatch},
+    models::{
+        account_model::{ForgotPasswordDTO, ResetPasswordDTO, VerifyEmailDTO},
+        lockout_model::LockoutEntity,
+        oidc_model::OidcCallbackQuery,
+        profile_model::ProfileEntity,
+        session_model::{RefreshTokenDTO, SessionEntity},
+        two_factor_model::{
+            EnrolmentBody, LoginBody, RecoveryCodesBody, RolePolicyEntity, TwoFactorChallengeBody,
+            TwoFactorCodeDTO, TwoFactorLoginDTO, UpdateRolePolicyDTO,
+        },
+        user_model::{CreateUserDTO, LoginUserDTO, UpdateRoleDTO, UserBody},
+    },
+    services::{
+        account_service, lockout_service, oidc_service, profile_service, session_service,
+        two_factor_service,
+    },
+    validation::{field_error, Path, Query, ValidatedRequest},
     AppState,
 };
 use crate::{models::user_model::UpdateUserDTO, services::user_service};
 use crate::{validation::CustomError, Result};
 use axum::{
-    extract::Path,
-    http::StatusCode,
+    headers::Cookie,
+    http::{header::SET_COOKIE, StatusCode},
+    response::{IntoResponse, Redirect, Response},
     routing::{delete, get, patch, post},
-    Extension, Json, Router,
+    Extension, Json, Router, TypedHeader,
 };
+use chrono::Duration;
 
-async fn create_user(
-    state: Extension<AppState>,
-    ValidatedRequest(data): ValidatedRequest<CreateUserDTO>,
+/// Opens a new session for `user` and hands out its access and refresh tokens.
+async fn start_session(
+    state: &Extension<AppState>,
+    user: ProfileEntity,
+    client: ClientInfo,
 ) -> Result<Json<UserBody>> {
-    let user = user_service::create_user(data, &state.db).await?;
+    let (session, refresh_token) = session_service::create_session(
+        &state.db,
+        user.id,
+        client,
+        Duration::days(state.config.refresh_token_days),
+    )
+    .await?;
+
     Ok(Json(UserBody {
+        token: access_token(state, &user, session.id).await?,
+        refresh_token,
         id: user.id,
         name: user.name,
         email: user.email,
-        token: Claims::new(user.id).to_jwt(&state)?,
+        role: user.role,
+        verified: user.verified,
+        two_factor: user.two_factor,
     }))
 }
 
+async fn access_token(
+    state: &Extension<AppState>,
+    user: &ProfileEntity,
+    sid: i32,
+) -> Result<String> {
+    let two_factor_missing =
+        !user.two_factor && two_factor_service::is_required(&state.db, user.role).await?;
+
+    Claims::new(
+        user.id,
+        user.role,
+        user.verified,
+        two_factor_missing,
+        sid,
+        Duration::minutes(state.config.access_token_minutes),
+    )
+    .to_jwt(state)
+}
+
+async fn create_user(
+    state: Extension<AppState>,
+    client: ClientInfo,
+    ValidatedRequest(data): ValidatedRequest<CreateUserDTO>,
+) -> Result<Json<UserBody>> {
+    if !state.config.password_login {
+        return Err(CustomError::PasswordLoginDisabled);
+    }
+    let user = user_service::create_user(data, &Actor::new(None, &client), &state.db).await?;
+
+    // the account exists either way, the mail can be asked for again
+    if let Err(e) = account_service::send_verification(&state, user.id, &user.email).await {
+        tracing::error!("Could not send verification mail: {:?}", e);
+    }
+
+    start_session(&state, user, client).await
+}
+
 async fn login_user(
     state: Extension<AppState>,
+    client: ClientInfo,
     ValidatedRequest(data): ValidatedRequest<LoginUserDTO>,
+) -> Result<Json<LoginBody>> {
+    if !state.config.password_login {
+        return Err(CustomError::PasswordLoginDisabled);
+    }
+    let email = data.email.clone();
+    let ip = client.ip.clone();
+    lockout_service::check(&state.db, &email, ip.as_deref()).await?;
+
+    match user_service::login_user(data, &state.db).await {
+        Ok(user) => {
+            // the login isn't over while the second factor is to come
+            if !user.two_factor {
+                lockout_service::record_success(&state.db, &email).await?;
+            }
+            finish_login(&state, user, client).await
+        }
+        Err(CustomError::Unauthorized) => {
+            lockout_service::record_failure(&state.db, &state.config, &email, ip.as_deref())
+                .await?;
+            Err(CustomError::Unauthorized)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Starts a session for `user`, unless the account still has to pass its second factor.
+async fn finish_login(
+    state: &Extension<AppState>,
+    user: ProfileEntity,
+    client: ClientInfo,
+) -> Result<Json<LoginBody>> {
+    if user.two_factor {
+        let challenge = two_factor_service::issue_challenge(state, user.id).await?;
+        return Ok(Json(LoginBody::TwoFactor(TwoFactorChallengeBody {
+            two_factor_required: true,
+            challenge,
+        })));
+    }
+
+    let Json(body) = start_session(state, user, client).await?;
+    Ok(Json(LoginBody::Session(body)))
+}
+
+/// Sends the user off to log in at the OpenID Connect provider.
+async fn oidc_login(state: Extension<AppState>) -> Result<Response> {
+    let provider = state.oidc.as_ref().ok_or(CustomError::NotFound)?;
+    let (url, oidc_state) = oidc_service::start_login(&state.db, provider).await?;
+    let cookie = oidc_service::state_cookie(Some(&oidc_state), secure_cookies(&state));
+
+    Ok(([(SET_COOKIE, cookie)], Redirect::to(&url)).into_response())
+}
+
+/// Cookies are only sent back over HTTPS when the provider redirects there.
+fn secure_cookies(state: &AppState) -> bool {
+    state.config.oidc_redirect_url.starts_with("https://")
+}
+
+async fn oidc_callback(
+    state: Extension<AppState>,
+    client: ClientInfo,
+    cookies: Option<TypedHeader<Cookie>>,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<Response> {
+    let provider = state.oidc.as_ref().ok_or(CustomError::NotFound)?;
+
+    let (Some(code), Some(oidc_state)) = (query.code, query.state) else {
+        tracing::warn!(
+            "Provider login failed: {:?} {:?}",
+            query.error,
+            query.error_description
+        );
+        return Err(CustomError::Unauthorized);
+    };
+
+    let browser_state = cookies
+        .as_ref()
+        .and_then(|TypedHeader(cookies)| cookies.get(oidc_service::STATE_COOKIE));
+    let actor = Actor::new(None, &client);
+    let user = oidc_service::finish_login(
+        &state.db,
+        provider,
+        &code,
+        &oidc_state,
+        browser_state,
+        &actor,
+    )
+    .await?;
+    let body = finish_login(&state, user, client).await?;
+    let cookie = oidc_service::state_cookie(None, secure_cookies(&state));
+
+    Ok(([(SET_COOKIE, cookie)], body).into_response())
+}
+
+/// Second step of a login for accounts with two-factor enabled. Wrong codes
+/// count towards the same lockout as wrong passwords.
+async fn login_two_factor(
+    state: Extension<AppState>,
+    client: ClientInfo,
+    ValidatedRequest(data): ValidatedRequest<TwoFactorLoginDTO>,
 ) -> Result<Json<UserBody>> {
-    let user = user_service::login_user(data, &state.db).await?;
+    let user_id = two_factor_service::challenge_user(&state, &data.challenge)?;
+    let user = profile_service::get_user(user_id, &state.db)
+        .await?
+        .filter(|user| user.deleted_at.is_none())
+        .ok_or(CustomError::Unauthorized)?;
+    let ip = client.ip.clone();
+    lockout_service::check(&state.db, &user.email, ip.as_deref()).await?;
+
+    if !two_factor_service::verify_code(&state.db, user.id, &data.code).await? {
+        lockout_service::record_failure(&state.db, &state.config, &user.email, ip.as_deref())
+            .await?;
+        return Err(field_error("code", "invalid code"));
+    }
+
+    two_factor_service::complete_challenge(&state, &data.challenge).await?;
+    lockout_service::record_success(&state.db, &user.email).await?;
+
+    start_session(&state, user, client).await
+}
+
+async fn refresh(
+    state: Extension<AppState>,
+    client: ClientInfo,
+    ValidatedRequest(data): ValidatedRequest<RefreshTokenDTO>,
+) -> Result<Json<UserBody>> {
+    let (session, refresh_token) = session_service::rotate_session(
+        &state.db,
+        &data.refresh_token,
+        client,
+        Duration::days(state.config.refresh_token_days),
+    )
+    .await?;
+
+    // the role is read again so changes to it reach the next access token
+    let user = profile_service::get_user(session.user_id, &state.db)
+        .await?
+        .ok_or(CustomError::Unauthorized)?;
 
     Ok(Json(UserBody {
+        token: access_token(&state, &user, session.id).await?,
+        refresh_token,
         id: user.id,
         name: user.name,
         email: user.email,
-        token: Claims::new(user.id).to_jwt(&state)?,
+        role: user.role,
+        verified: user.verified,
+        two_factor: user.two_factor,
     }))
 }
 
-async fn get_current_user(state: Extension<AppState>, claims: Claims) -> Result<Json<UserBody>> {
+async fn logout(state: Extension<AppState>, claims: Claims) -> Result<StatusCode> {
+    session_service::revoke_session(&state.db, claims.session()?).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn logout_everywhere(state: Extension<AppState>, claims: Claims) -> Result<StatusCode> {
+    claims.session()?;
+    session_service::revoke_all_sessions(&state.db, claims.sub).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn get_sessions(
+    state: Extension<AppState>,
+    claims: Claims,
+) -> Result<Json<Vec<SessionEntity>>> {
+    let sessions = session_service::get_active_sessions(&state.db, claims.sub).await?;
+    Ok(Json(sessions))
+}
+
+async fn revoke_session(
+    state: Extension<AppState>,
+    claims: Claims,
+    Path(id): Path<i32>,
+) -> Result<StatusCode> {
+    claims.session()?;
+    let session = session_service::get_session(&state.db, id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+    claims.ensure_self_or(session.user_id, Permission::ManageUsers)?;
+
+    session_service::revoke_session(&state.db, id).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn verify_email(
+    state: Extension<AppState>,
+    actor: Actor,
+    ValidatedRequest(data): ValidatedRequest<VerifyEmailDTO>,
+) -> Result<StatusCode> {
+    account_service::verify_email(&state, &data.token, &actor).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn resend_verification(state: Extension<AppState>, claims: Claims) -> Result<StatusCode> {
+    let user = profile_service::get_user(claims.sub, &state.db)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+
+    if !user.verified {
+        account_service::send_verification(&state, user.id, &user.email).await?;
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn forgot_password(
+    state: Extension<AppState>,
+    ValidatedRequest(data): ValidatedRequest<ForgotPasswordDTO>,
+) -> Result<StatusCode> {
+    account_service::request_password_reset(&state, &data.email).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn reset_password(
+    state: Extension<AppState>,
+    actor: Actor,
+    ValidatedRequest(data): ValidatedRequest<ResetPasswordDTO>,
+) -> Result<StatusCode> {
+    account_service::reset_password(&state, data, &actor).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn enrol_two_factor(
+    state: Extension<AppState>,
+    claims: Claims,
+) -> Result<Json<EnrolmentBody>> {
+    claims.session()?;
+    let user = profile_service::get_user(claims.sub, &state.db)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+    let enrolment = two_factor_service::enrol(&state.db, user.id, &user.email).await?;
+
+    Ok(Json(enrolment))
+}
+
+/// Confirms the enrolment. The session keeps going, but tokens only reflect
+/// the change once refreshed.
+async fn confirm_two_factor(
+    state: Extension<AppState>,
+    claims: Claims,
+    actor: Actor,
+    ValidatedRequest(data): ValidatedRequest<TwoFactorCodeDTO>,
+) -> Result<Json<RecoveryCodesBody>> {
+    claims.session()?;
+    let codes = two_factor_service::confirm(&state.db, claims.sub, &data.code, &actor).await?;
+    Ok(Json(codes))
+}
+
+/// Checks a code of the second factor of the user's own account. Wrong codes
+/// count towards the same lockout as wrong passwords, so an access token is
+/// no license to guess them.
+async fn verify_own_code(
+    state: &AppState,
+    user_id: i32,
+    code: &str,
+    client: &ClientInfo,
+) -> Result<()> {
+    let user = profile_service::get_user(user_id, &state.db)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+    let ip = client.ip.as_deref();
+    lockout_service::check(&state.db, &user.email, ip).await?;
+
+    if !two_factor_service::verify_code(&state.db, user.id, code).await? {
+        lockout_service::record_failure(&state.db, &state.config, &user.email, ip).await?;
+        return Err(field_error("code", "invalid code"));
+    }
+
+    lockout_service::record_success(&state.db, &user.email).await
+}
+
+async fn regenerate_recovery_codes(
+    state: Extension<AppState>,
+    claims: Claims,
+    actor: Actor,
+    client: ClientInfo,
+    ValidatedRequest(data): ValidatedRequest<TwoFactorCodeDTO>,
+) -> Result<Json<RecoveryCodesBody>> {
+    claims.session()?;
+    verify_own_code(&state, claims.sub, &data.code, &client).await?;
+
+    let codes =
+        two_factor_service::regenerate_recovery_codes(&state.db, claims.sub, &actor).await?;
+    Ok(Json(codes))
+}
+
+async fn disable_two_factor(
+    state: Extension<AppState>,
+    claims: Claims,
+    actor: Actor,
+    client: ClientInfo,
+    ValidatedRequest(data): ValidatedRequest<TwoFactorCodeDTO>,
+) -> Result<StatusCode> {
+    claims.session()?;
+    verify_own_code(&state, claims.sub, &data.code, &client).await?;
+
+    two_factor_service::disable(&state.db, claims.sub, &actor).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn reset_two_factor(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageUsers>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<StatusCode> {
+    profile_service::get_user(id, &state.db)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+
+    two_factor_service::disable(&state.db, id, &actor).await?;
+    session_service::revoke_all_sessions(&state.db, id).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn get_two_factor_policies(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageUsers>,
+) -> Result<Json<Vec<RolePolicyEntity>>> {
+    let policies = two_factor_service::get_policies(&state.db).await?;
+    Ok(Json(policies))
+}
+
+async fn update_two_factor_policy(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageUsers>,
+    actor: Actor,
+    ValidatedRequest(data): ValidatedRequest<UpdateRolePolicyDTO>,
+) -> Result<Json<RolePolicyEntity>> {
+    let policy = two_factor_service::update_policy(&state.db, data, &actor).await?;
+    Ok(Json(policy))
+}
+
+async fn get_lockouts(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageUsers>,
+) -> Result<Json<Vec<LockoutEntity>>> {
+    let lockouts = lockout_service::get_active_lockouts(&state.db).await?;
+    Ok(Json(lockouts))
+}
+
+async fn unlock_lockout(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageUsers>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<LockoutEntity>> {
+    let lockout = lockout_service::unlock(&state.db, id, &actor).await?;
+
+    match lockout {
+        Some(lockout) => Ok(Json(lockout)),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn unlock_user(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageUsers>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<StatusCode> {
+    lockout_service::unlock_user(&state.db, id, &actor).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn get_current_user(
+    state: Extension<AppState>,
+    claims: Claims,
+) -> Result<Json<ProfileEntity>> {
     let user = profile_service::get_user(claims.sub, &state.db).await?;
     match user {
-        Some(user) => Ok(Json(UserBody {
-            id: user.id,
-            name: user.name,
-            email: user.email,
-            token: Claims::new(user.id).to_jwt(&state)?,
-        })),
+        Some(user) => Ok(Json(user)),
         None => Err(CustomError::NotFound),
     }
 }
 
 async fn update_user(
     state: Extension<AppState>,
+    claims: Claims,
+    actor: Actor,
     Path(id): Path<i32>,
+    if_match: IfMatch,
     ValidatedRequest(data): ValidatedRequest<UpdateUserDTO>,
-) -> Result<Json<UserEntity>> {
-    let user = user_service::update_user(id, data, &state.db).await?;
+) -> Result<Response> {
+    claims.session()?;
+    claims.ensure_self_or(id, Permission::ManageUsers)?;
+    let user =
+        user_service::update_user(id, data, &if_match, claims.sid, &actor, &state.db).await?;
 
-    Ok(Json(user))
+    match user {
+        Some(user) => etag::tagged(user),
+        None => Err(CustomError::NotFound),
+    }
 }
 
-async fn delete_user(state: Extension<AppState>, Path(id): Path<i32>) -> Result<StatusCode> {
-    user_service::delete_user(id, &state.db).await?;
+async fn update_role(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageUsers>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+    ValidatedRequest(data): ValidatedRequest<UpdateRoleDTO>,
+) -> Result<Response> {
+    let user = user_service::update_role(id, data.role, &if_match, &actor, &state.db).await?;
+
+    match user {
+        Some(user) => etag::tagged(user),
+        None => Err(CustomError::NotFound),
+    }
+}
+
+async fn delete_user(
+    state: Extension<AppState>,
+    claims: Claims,
+    actor: Actor,
+    Path(id): Path<i32>,
+    if_match: IfMatch,
+) -> Result<StatusCode> {
+    claims.session()?;
+    claims.ensure_self_or(id, Permission::ManageUsers)?;
+    user_service::delete_user(id, &if_match, &actor, &state.db).await?;
     Ok(StatusCode::OK)
 }
 
+async fn restore_user(
+    state: Extension<AppState>,
+    _auth: Authorized<can::ManageUsers>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<ProfileEntity>> {
+    let user = user_service::restore_user(id, &actor, &state.db).await?;
+
+    match user {
+        Some(user) => Ok(Json(user)),
+        None => Err(CustomError::NotFound),
+    }
+}
+
 fn real_route() -> Router {
     Router::new()
         .route("/", get(get_current_user))
         .route("/me", get(get_current_user))
         .route("/create", post(create_user))
         .route("/login", post(login_user))
+        .route("/login/2fa", post(login_two_factor))
+        .route("/oidc/login", get(oidc_login))
+        .route("/oidc/callback", get(oidc_callback))
+        .route("/refresh", post(refresh))
+        .route("/logout", post(logout))
+        .route("/logout/all", post(logout_everywhere))
+        .route("/sessions", get(get_sessions))
+        .route("/sessions/:id", delete(revoke_session))
+        .route("/verify", post(verify_email))
+        .route("/verify/resend", post(resend_verification))
+        .route("/password/forgot", post(forgot_password))
+        .route("/password/reset", post(reset_password))
+        .route("/2fa/enrol", post(enrol_two_factor))
+        .route("/2fa/confirm", post(confirm_two_factor))
+        .route("/2fa/recovery-codes", post(regenerate_recovery_codes))
+        .route("/2fa/disable", post(disable_two_factor))
+        .route("/2fa/reset/:id", post(reset_two_factor))
+        .route(
+            "/2fa/policy",
+            get(get_two_factor_policies).put(update_two_factor_policy),
+        )
         .route("/update/:id", patch(update_user))
+        .route("/role/:id", patch(update_role))
+        .route("/lockouts", get(get_lockouts))
+        .route("/lockouts/:id/unlock", post(unlock_lockout))
+        .route("/unlock/:id", post(unlock_user))
         .route("/delete/:id", delete(delete_user))
+        .route("/restore/:id", post(restore_user))
 }
 
 pub fn route() -> Router {
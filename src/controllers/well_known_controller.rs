@@ -0,0 +1,24 @@
+use axum::{
+    http::header::{HeaderMap, HeaderValue, CACHE_CONTROL},
+    routing::get,
+    Extension, Json, Router,
+};
+use jsonwebtoken::jwk::JwkSet;
+
+use crate::AppState;
+
+/// Public keys access tokens are signed with, so other services can verify
+/// them. Kept short in caches so rotations are picked up quickly.
+async fn get_jwks(state: Extension<AppState>) -> (HeaderMap, Json<JwkSet>) {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=300"),
+    );
+
+    (headers, Json(state.keys.jwks()))
+}
+
+pub fn route() -> Router {
+    Router::new().route("/.well-known/jwks.json", get(get_jwks))
+}
@@ -0,0 +1,169 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{
+        header::{ETAG, IF_MATCH, IF_NONE_MATCH},
+        request::Parts,
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{validation::CustomError, AppState, Result};
+
+/// The entity tag of what a resource looks like right now, which changes
+/// whenever any of its fields does.
+pub fn etag<T: Serialize>(entity: &T) -> Result<String> {
+    let json = serde_json::to_vec(entity).map_err(anyhow::Error::from)?;
+    let hash = Sha256::digest(json);
+
+    Ok(format!("\"{}\"", hex::encode(&hash[..16])))
+}
+
+/// Responds with `entity` as JSON along with its `ETag`.
+pub fn tagged<T: Serialize>(entity: T) -> Result<Response> {
+    let etag = etag(&entity)?;
+
+    Ok(([(ETAG, etag)], Json(entity)).into_response())
+}
+
+/// Whether a list of entity tags such as `"a", W/"b"` holds `etag`. Weak
+/// tags only count when `weak` is set.
+pub fn matches(list: &str, etag: &str, weak: bool) -> bool {
+    list.split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == etag || (weak && tag.strip_prefix("W/") == Some(etag)))
+}
+
+/// The `If-Match` header of a write, telling which version of the resource
+/// the client means to change. Writes without one go through unless
+/// `require_if_match` is on.
+#[derive(Debug, Clone)]
+pub struct IfMatch(Option<String>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for IfMatch
+where
+    S: Send + Sync,
+{
+    type Rejection = CustomError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Extension(ctx) = Extension::<AppState>::from_request_parts(parts, state)
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        let if_match = header(&parts.headers, IF_MATCH);
+        if if_match.is_none() && ctx.config.require_if_match {
+            return Err(CustomError::PreconditionRequired);
+        }
+
+        Ok(Self(if_match))
+    }
+}
+
+impl IfMatch {
+    /// Fails unless the client saw `current` last, which is `None` when the
+    /// resource doesn't exist. To be called once it is locked, so it can't
+    /// change between the check and the write.
+    pub fn check<T: Serialize>(&self, current: Option<&T>) -> Result<()> {
+        let Some(if_match) = &self.0 else {
+            return Ok(());
+        };
+
+        match current {
+            Some(current) if matches(if_match, &etag(current)?, false) => Ok(()),
+            _ => Err(CustomError::PreconditionFailed),
+        }
+    }
+}
+
+/// The `If-None-Match` header of a read, listing the versions of the
+/// resource the client already has.
+#[derive(Debug, Clone)]
+pub struct IfNoneMatch(Option<String>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for IfNoneMatch
+where
+    S: Send + Sync,
+{
+    type Rejection = CustomError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(header(&parts.headers, IF_NONE_MATCH)))
+    }
+}
+
+impl IfNoneMatch {
+    /// Responds like [`tagged`], or with `304 Not Modified` when the client
+    /// has that version already.
+    pub fn respond<T: Serialize>(&self, entity: T) -> Result<Response> {
+        let etag = etag(&entity)?;
+
+        match &self.0 {
+            Some(if_none_match) if matches(if_none_match, &etag, true) => {
+                Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response())
+            }
+            _ => Ok(([(ETAG, etag)], Json(entity)).into_response()),
+        }
+    }
+}
+
+fn header(headers: &HeaderMap, name: axum::http::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn matches_strong_tags() {
+        assert!(matches("\"a\"", "\"a\"", false));
+        assert!(matches("\"b\", \"a\"", "\"a\"", false));
+        assert!(!matches("\"b\"", "\"a\"", false));
+        assert!(!matches("", "\"a\"", false));
+    }
+
+    #[test]
+    fn matches_weak_tags_only_when_asked() {
+        assert!(!matches("W/\"a\"", "\"a\"", false));
+        assert!(matches("W/\"a\"", "\"a\"", true));
+        assert!(matches("\"b\",W/\"a\"", "\"a\"", true));
+    }
+
+    #[test]
+    fn matches_anything_with_a_star() {
+        assert!(matches("*", "\"a\"", false));
+        assert!(matches("*", "\"a\"", true));
+    }
+
+    #[test]
+    fn check_compares_with_the_current_version() {
+        let current = serde_json::json!({ "id": 1, "name": "Shelf" });
+        let tag = etag(&current).unwrap();
+
+        assert!(IfMatch(None).check::<Value>(None).is_ok());
+        assert!(IfMatch(Some(tag.clone())).check(Some(&current)).is_ok());
+        assert!(IfMatch(Some("*".to_string())).check(Some(&current)).is_ok());
+
+        let changed = serde_json::json!({ "id": 1, "name": "Rack" });
+        assert!(matches!(
+            IfMatch(Some(tag.clone())).check(Some(&changed)),
+            Err(CustomError::PreconditionFailed)
+        ));
+        assert!(matches!(
+            IfMatch(Some(tag)).check::<Value>(None),
+            Err(CustomError::PreconditionFailed)
+        ));
+    }
+}
@@ -0,0 +1,169 @@
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters,
+        OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::config::Config;
+
+/// A key access tokens are signed or verified with.
+struct SigningKey {
+    /// `None` only for the HMAC fallback, which has nothing to publish.
+    kid: Option<String>,
+    algorithm: Algorithm,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    jwk: Option<Jwk>,
+}
+
+/// The keys access tokens are signed with. The first one signs new tokens,
+/// the rest only verify the tokens they signed before being rotated out.
+pub struct KeyRing {
+    keys: Vec<SigningKey>,
+}
+
+impl KeyRing {
+    pub fn from_config(cfg: &Config) -> anyhow::Result<Self> {
+        if cfg.jwt_keys.is_empty() {
+            return Ok(Self {
+                keys: vec![SigningKey {
+                    kid: None,
+                    algorithm: Algorithm::HS256,
+                    encoding: EncodingKey::from_secret(cfg.hmac_key.as_bytes()),
+                    decoding: DecodingKey::from_secret(cfg.hmac_key.as_bytes()),
+                    jwk: None,
+                }],
+            });
+        }
+
+        let keys = cfg
+            .jwt_keys
+            .iter()
+            .map(|path| load_key(path).with_context(|| format!("invalid key {}", path.display())))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        for (i, key) in keys.iter().enumerate() {
+            if keys[..i].iter().any(|other| other.kid == key.kid) {
+                bail!("more than one key with kid {:?}", key.kid);
+            }
+        }
+
+        Ok(Self { keys })
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> anyhow::Result<String> {
+        let key = &self.keys[0];
+        let mut header = Header::new(key.algorithm);
+        header.kid = key.kid.clone();
+
+        encode(&header, claims, &key.encoding).context("could not encode JWT")
+    }
+
+    /// Checks `token` against the key named by its `kid`, returning its claims
+    /// if the signature holds and it hasn't expired.
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Option<T> {
+        let header = decode_header(token).ok()?;
+        let key = self.keys.iter().find(|key| key.kid == header.kid)?;
+
+        decode::<T>(token, &key.decoding, &Validation::new(key.algorithm))
+            .ok()
+            .map(|data| data.claims)
+    }
+
+    /// The public halves of every key, for other services to verify our tokens.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().filter_map(|key| key.jwk.clone()).collect(),
+        }
+    }
+}
+
+/// Reads a PEM private key, RSA (PKCS#1 or PKCS#8) or Ed25519 (PKCS#8). Its
+/// `kid` is the file name without the extension.
+fn load_key(path: &Path) -> anyhow::Result<SigningKey> {
+    let kid = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .context("can not tell the kid from the file name")?
+        .to_string();
+    let bytes = std::fs::read(path).context("could not read key")?;
+    let pem = pem::parse(&bytes).context("not a PEM file")?;
+
+    let (algorithm, encoding, decoding, params) = match pem.tag.as_str() {
+        "RSA PRIVATE KEY" => {
+            let pair = RsaKeyPair::from_der(&pem.contents)
+                .map_err(|e| anyhow::anyhow!("invalid RSA key: {}", e))?;
+            rsa_key(&bytes, &pair)?
+        }
+        "PRIVATE KEY" => {
+            if let Ok(pair) = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&pem.contents) {
+                let x = URL_SAFE_NO_PAD.encode(pair.public_key().as_ref());
+                (
+                    Algorithm::EdDSA,
+                    EncodingKey::from_ed_pem(&bytes)?,
+                    DecodingKey::from_ed_components(&x)?,
+                    AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                        key_type: OctetKeyPairType::OctetKeyPair,
+                        curve: EllipticCurve::Ed25519,
+                        x,
+                    }),
+                )
+            } else {
+                let pair = RsaKeyPair::from_pkcs8(&pem.contents)
+                    .map_err(|e| anyhow::anyhow!("neither an Ed25519 nor an RSA key: {}", e))?;
+                rsa_key(&bytes, &pair)?
+            }
+        }
+        tag => bail!("expected a private key, found {}", tag),
+    };
+
+    Ok(SigningKey {
+        kid: Some(kid.clone()),
+        algorithm,
+        encoding,
+        decoding,
+        jwk: Some(Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                algorithm: Some(algorithm),
+                key_id: Some(kid),
+                ..Default::default()
+            },
+            algorithm: params,
+        }),
+    })
+}
+
+fn rsa_key(
+    pem: &[u8],
+    pair: &RsaKeyPair,
+) -> anyhow::Result<(Algorithm, EncodingKey, DecodingKey, AlgorithmParameters)> {
+    let n = pair
+        .public_key()
+        .modulus()
+        .big_endian_without_leading_zero();
+    let e = pair
+        .public_key()
+        .exponent()
+        .big_endian_without_leading_zero();
+
+    Ok((
+        Algorithm::RS256,
+        EncodingKey::from_rsa_pem(pem)?,
+        DecodingKey::from_rsa_raw_components(n, e),
+        AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: URL_SAFE_NO_PAD.encode(n),
+            e: URL_SAFE_NO_PAD.encode(e),
+        }),
+    ))
+}
@@ -1,16 +1,31 @@
 use std::{net::SocketAddr, sync::Arc};
 
-use axum::{Extension, Router};
+use axum::{middleware, Extension, Router};
 use sqlx::PgPool;
-use tower_http::trace::TraceLayer;
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
 
-use crate::{config::Config, validation::CustomError};
+use crate::{
+    config::Config, keys::KeyRing, mailer::Mailer, oidc::OidcProvider, storage::Storage,
+    validation::CustomError,
+};
 
 mod authorization;
+mod client;
 pub mod config;
 mod controllers;
+mod etag;
+mod keys;
+mod mailer;
 mod models;
+mod oidc;
+mod pagination;
+mod password;
+mod problem;
 mod services;
+mod storage;
 mod validation;
 
 pub type Result<T, E = CustomError> = std::result::Result<T, E>;
@@ -19,29 +34,60 @@ pub type Result<T, E = CustomError> = std::result::Result<T, E>;
 pub struct AppState {
     db: PgPool,
     config: Arc<Config>,
+    keys: Arc<KeyRing>,
+    mailer: Arc<dyn Mailer>,
+    oidc: Option<Arc<OidcProvider>>,
+    storage: Arc<dyn Storage>,
 }
 
 pub async fn server(db: PgPool, cfg: Config) -> anyhow::Result<()> {
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+    password::init(&cfg)?;
     let state = AppState {
         db,
+        keys: Arc::new(KeyRing::from_config(&cfg)?),
+        mailer: mailer::from_config(&cfg)?,
+        oidc: oidc::from_config(&cfg)?,
+        storage: storage::from_config(&cfg)?,
         config: Arc::new(cfg),
     };
     let app = api_router()
         .layer(TraceLayer::new_for_http())
-        .layer(Extension(state));
+        .layer(Extension(state))
+        .layer(middleware::from_fn(problem::scope_request_id))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .layer(middleware::map_request(client::drop_request_id));
 
     println!("Listening on http://{}", addr);
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
 }
 
+/// Makes the user with that email address an admin, `false` if there is no
+/// such user. For installations that have no admin to hand out roles.
+pub async fn promote_admin(db: &PgPool, email: &str) -> anyhow::Result<bool> {
+    let user =
+        services::user_service::promote_admin(email, &authorization::Actor::default(), db).await?;
+
+    Ok(user.is_some())
+}
+
 fn api_router() -> Router {
     Router::new()
         .merge(controllers::user_controller::route())
         .merge(controllers::profile_controller::route())
         .merge(controllers::place_controller::route())
+        .merge(controllers::item_controller::route())
+        .merge(controllers::stock_controller::route())
+        .merge(controllers::loan_controller::route())
+        .merge(controllers::requisition_controller::route())
+        .merge(controllers::search_controller::route())
+        .merge(controllers::image_controller::route())
+        .merge(controllers::api_key_controller::route())
+        .merge(controllers::audit_controller::route())
+        .merge(controllers::well_known_controller::route())
 }
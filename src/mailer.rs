@@ -0,0 +1,129 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use axum::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::config::{Config, MailerKind};
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()>;
+}
+
+pub fn from_config(cfg: &Config) -> anyhow::Result<Arc<dyn Mailer>> {
+    let from: Mailbox = cfg.mail_from.parse().context("invalid mail_from")?;
+
+    Ok(match cfg.mailer {
+        MailerKind::Smtp => {
+            let url = cfg
+                .smtp_url
+                .as_deref()
+                .context("smtp_url is required by the smtp mailer")?;
+            Arc::new(SmtpMailer::new(url, from)?)
+        }
+        MailerKind::File => Arc::new(FileMailer::new(cfg.mail_dir.clone(), from)),
+        MailerKind::Console => Arc::new(ConsoleMailer),
+    })
+}
+
+fn build_message(from: &Mailbox, mail: Mail) -> anyhow::Result<Message> {
+    Message::builder()
+        .from(from.clone())
+        .to(mail.to.parse().context("invalid recipient")?)
+        .subject(mail.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(mail.body)
+        .context("could not build mail")
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(url: &str, from: Mailbox) -> anyhow::Result<Self> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .context("invalid smtp_url")?
+            .build();
+
+        Ok(Self { transport, from })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        let message = build_message(&self.from, mail)?;
+        self.transport
+            .send(message)
+            .await
+            .context("could not send mail")?;
+
+        Ok(())
+    }
+}
+
+/// Writes every mail as an `.eml` file, handy for development and tests.
+pub struct FileMailer {
+    transport: AsyncFileTransport<Tokio1Executor>,
+    dir: PathBuf,
+    from: Mailbox,
+}
+
+impl FileMailer {
+    pub fn new(dir: PathBuf, from: Mailbox) -> Self {
+        Self {
+            transport: AsyncFileTransport::new(&dir),
+            dir,
+            from,
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .context("could not create mail_dir")?;
+
+        let message = build_message(&self.from, mail)?;
+        let id = self
+            .transport
+            .send(message)
+            .await
+            .context("could not write mail")?;
+        tracing::info!("Mail written to {}/{}.eml", self.dir.display(), id);
+
+        Ok(())
+    }
+}
+
+/// Only logs mail, nothing leaves the machine.
+pub struct ConsoleMailer;
+
+#[async_trait]
+impl Mailer for ConsoleMailer {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        tracing::info!(
+            "Mail to {}\nSubject: {}\n\n{}",
+            mail.to,
+            mail.subject,
+            mail.body
+        );
+
+        Ok(())
+    }
+}
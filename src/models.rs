@@ -1,3 +1,15 @@
+pub mod account_model;
+pub mod api_key_model;
+pub mod audit_model;
+pub mod item_model;
+pub mod loan_model;
+pub mod lockout_model;
+pub mod oidc_model;
 pub mod place_model;
 pub mod profile_model;
+pub mod requisition_model;
+pub mod search_model;
+pub mod session_model;
+pub mod stock_model;
+pub mod two_factor_model;
 pub mod user_model;
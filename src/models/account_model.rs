@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "action_token_purpose", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TokenPurpose {
+    PasswordReset,
+    EmailVerification,
+    TwoFactorLogin,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct VerifyEmailDTO {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ForgotPasswordDTO {
+    #[validate(email(message = "Invalid email"))]
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ResetPasswordDTO {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub token: String,
+    #[validate(custom = "crate::password::validate_password")]
+    pub password: String,
+}
@@ -0,0 +1,35 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::authorization::Permission;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyEntity {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Permission>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A freshly created key, the only time its secret is shown.
+#[derive(Serialize, Deserialize)]
+pub struct ApiKeyBody {
+    #[serde(flatten)]
+    pub api_key: ApiKeyEntity,
+    pub key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateApiKeyDTO {
+    #[validate(length(min = 1, max = 255, message = "Must have between 1 and 255 characters"))]
+    pub name: String,
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub scopes: Vec<Permission>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
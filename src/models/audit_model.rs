@@ -0,0 +1,54 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "audit_action", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    Restore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "audit_entity", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEntityType {
+    Place,
+    Item,
+    User,
+    StockMovement,
+    StockTransfer,
+    Loan,
+    Requisition,
+    ApiKey,
+    Lockout,
+    RolePolicy,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntity {
+    pub id: i32,
+    pub actor_id: Option<i32>,
+    pub action: AuditAction,
+    pub entity_type: AuditEntityType,
+    pub entity_id: i32,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub request_id: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub actor_id: Option<i32>,
+    pub action: Option<AuditAction>,
+    pub entity_type: Option<AuditEntityType>,
+    pub entity_id: Option<i32>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Newest entries are returned first, at most 100 at a time.
+    pub limit: Option<i64>,
+}
@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ItemEntity {
+    pub id: i32,
+    pub sku: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub unit: String,
+    /// Name of the uploaded image, served under `/images/` and its thumbnail
+    /// under `/images/thumbnails/`.
+    pub image: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateItemDTO {
+    #[validate(length(min = 1, max = 64, message = "Must have between 1 and 64 characters"))]
+    pub sku: String,
+    #[validate(length(min = 1, max = 255, message = "Must have between 1 and 255 characters"))]
+    pub name: String,
+    #[validate(length(max = 255, message = "Must have at most 255 characters"))]
+    pub description: Option<String>,
+    #[validate(length(min = 1, max = 32, message = "Must have between 1 and 32 characters"))]
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateItemDTO {
+    #[validate(length(min = 1, max = 64, message = "Must have between 1 and 64 characters"))]
+    pub sku: Option<String>,
+    #[validate(length(min = 1, max = 255, message = "Must have between 1 and 255 characters"))]
+    pub name: Option<String>,
+    #[validate(length(max = 255, message = "Must have at most 255 characters"))]
+    pub description: Option<String>,
+    #[validate(length(min = 1, max = 32, message = "Must have between 1 and 32 characters"))]
+    pub unit: Option<String>,
+}
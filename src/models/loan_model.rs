@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::validation::invalid;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoanEntity {
+    pub id: i32,
+    pub item_id: i32,
+    pub place_id: i32,
+    pub user_id: i32,
+    pub quantity: i32,
+    pub returned_quantity: i32,
+    pub due_at: DateTime<Utc>,
+    pub note: Option<String>,
+    pub returned_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoanReturnEntity {
+    pub id: i32,
+    pub loan_id: i32,
+    pub quantity: i32,
+    pub condition: Option<String>,
+    pub user_id: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CheckoutDTO {
+    pub item_id: i32,
+    pub place_id: i32,
+    #[validate(range(min = 1, message = "Must be positive"))]
+    pub quantity: i32,
+    #[validate(custom(function = "validate_due_at"))]
+    pub due_at: DateTime<Utc>,
+    #[validate(length(max = 255, message = "Must have at most 255 characters"))]
+    pub note: Option<String>,
+}
+
+fn validate_due_at(due_at: &DateTime<Utc>) -> Result<(), ValidationError> {
+    if *due_at <= Utc::now() {
+        return Err(invalid("due date must be in the future"));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ReturnLoanDTO {
+    #[validate(range(min = 1, message = "Must be positive"))]
+    pub quantity: i32,
+    #[validate(length(max = 255, message = "Must have at most 255 characters"))]
+    pub condition: Option<String>,
+}
@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "throttle_scope", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ThrottleScope {
+    Email,
+    Ip,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LockoutEntity {
+    pub id: i32,
+    pub scope: ThrottleScope,
+    pub key: String,
+    pub user_id: Option<i32>,
+    pub failures: i32,
+    pub locked_until: DateTime<Utc>,
+    pub unlocked_at: Option<DateTime<Utc>>,
+    pub unlocked_by: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
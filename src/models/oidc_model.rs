@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// Where the provider sends users back to, with either a code or an error.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
@@ -2,32 +2,249 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
+/// Mean radius of the Earth, in meters.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// A point on the map, in degrees.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Validate)]
 pub struct Coordinates {
+    #[validate(range(min = -90.0, max = 90.0, message = "Must be between -90 and 90"))]
     pub lat: f64,
+    #[validate(range(min = -180.0, max = 180.0, message = "Must be between -180 and 180"))]
     pub lng: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Coordinates {
+    /// Great-circle distance to `other` in meters, by the haversine formula.
+    pub fn distance_to(&self, other: &Coordinates) -> f64 {
+        let d_lat = (other.lat - self.lat).to_radians();
+        let d_lng = (other.lng - self.lng).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2)
+            + self.lat.to_radians().cos()
+                * other.lat.to_radians().cos()
+                * (d_lng / 2.0).sin().powi(2);
+
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+
+    /// Degrees of latitude and longitude no point within `radius` meters is
+    /// farther than, longitude being `None` when every one could be.
+    pub fn bounds(&self, radius: f64) -> (f64, Option<f64>) {
+        let d_lat = (radius / EARTH_RADIUS).to_degrees();
+        let cos = (self.lat.abs() + d_lat).min(90.0).to_radians().cos();
+        let d_lng = Some(d_lat / cos).filter(|d_lng| d_lng.is_finite() && *d_lng < 180.0);
+
+        (d_lat, d_lng)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct PlaceEntity {
     pub id: i32,
     pub name: String,
     pub description: Option<String>,
+    /// Name of the uploaded image, served under `/images/` and its thumbnail
+    /// under `/images/thumbnails/`.
     pub image: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// The place this one is inside of, `None` at the top.
+    pub parent_id: Option<i32>,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+}
+
+impl PlaceEntity {
+    pub fn coordinates(&self) -> Option<Coordinates> {
+        Some(Coordinates {
+            lat: self.lat?,
+            lng: self.lng?,
+        })
+    }
+}
+
+/// A place along with every place inside of it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlaceNode {
+    #[serde(flatten)]
+    pub place: PlaceEntity,
+    pub children: Vec<PlaceNode>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlaceQuery {
+    /// Lists deleted places as well, for those who manage places.
+    #[serde(default)]
+    pub include_deleted: bool,
+    /// Part of the name, in any case.
+    pub name: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+}
+
+/// Places around a point, as in `?lat=-23.56&lng=-46.73&radius=500`.
+#[derive(Debug, Deserialize, Validate)]
+pub struct NearbyQuery {
+    #[validate(range(min = -90.0, max = 90.0, message = "Must be between -90 and 90"))]
+    pub lat: f64,
+    #[validate(range(min = -180.0, max = 180.0, message = "Must be between -180 and 180"))]
+    pub lng: f64,
+    /// In meters.
+    #[validate(range(min = 1.0, max = 100000.0, message = "Must be between 1 and 100000"))]
+    pub radius: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NearbyPlace {
+    #[serde(flatten)]
+    pub place: PlaceEntity,
+    /// In meters.
+    pub distance: f64,
+}
+
+/// Places as a GeoJSON `FeatureCollection`, for drawing them on a map.
+#[derive(Debug, Serialize)]
+pub struct PlaceCollection {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub features: Vec<PlaceFeature>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlaceFeature {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub id: i32,
+    pub geometry: Point,
+    pub properties: PlaceProperties,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Point {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// Longitude first, as GeoJSON has it.
+    pub coordinates: [f64; 2],
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlaceProperties {
+    pub name: String,
+    pub description: Option<String>,
+    pub parent_id: Option<i32>,
+}
+
+impl PlaceCollection {
+    /// Leaves out the places that are not on the map.
+    pub fn new(places: Vec<PlaceEntity>) -> Self {
+        let features = places
+            .into_iter()
+            .filter_map(|place| {
+                let coordinates = place.coordinates()?;
+                Some(PlaceFeature {
+                    kind: "Feature",
+                    id: place.id,
+                    geometry: Point {
+                        kind: "Point",
+                        coordinates: [coordinates.lng, coordinates.lat],
+                    },
+                    properties: PlaceProperties {
+                        name: place.name,
+                        description: place.description,
+                        parent_id: place.parent_id,
+                    },
+                })
+            })
+            .collect();
+
+        Self {
+            kind: "FeatureCollection",
+            features,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct CreatePlaceDTO {
     pub name: String,
     pub description: Option<String>,
-    pub image: Option<String>,
+    pub parent_id: Option<i32>,
+    #[validate]
+    pub coordinates: Option<Coordinates>,
 }
 
+/// A partial update, fields left out are kept as they are and those set to
+/// `null` are cleared.
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct UpdatePlaceDTO {
-    pub id: i32,
+    #[serde(default, deserialize_with = "crate::validation::non_null")]
     pub name: Option<String>,
-    pub description: Option<String>,
-    pub image: Option<String>,
+    #[serde(default, deserialize_with = "crate::validation::nullable")]
+    pub description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "crate::validation::nullable")]
+    pub parent_id: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "crate::validation::nullable")]
+    #[validate]
+    pub coordinates: Option<Option<Coordinates>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(lat: f64, lng: f64) -> Coordinates {
+        Coordinates { lat, lng }
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn distance_along_a_meridian() {
+        // a degree of latitude is about 111.2 km everywhere
+        assert_close(at(0.0, 0.0).distance_to(&at(1.0, 0.0)), 111_195.0, 1.0);
+        assert_close(at(-23.0, -46.0).distance_to(&at(-23.0, -46.0)), 0.0, 1e-6);
+    }
+
+    #[test]
+    fn distance_across_the_antimeridian() {
+        let east = at(0.0, 179.99);
+        let west = at(0.0, -179.99);
+        assert_close(east.distance_to(&west), 2_224.0, 1.0);
+        assert_close(west.distance_to(&east), 2_224.0, 1.0);
+    }
+
+    #[test]
+    fn distance_over_a_pole() {
+        assert_close(at(89.99, 0.0).distance_to(&at(89.99, 180.0)), 2_224.0, 1.0);
+        assert_close(at(90.0, 0.0).distance_to(&at(90.0, 123.0)), 0.0, 1e-6);
+    }
+
+    #[test]
+    fn bounds_hold_the_circle() {
+        let center = at(-23.56, -46.73);
+        let radius = 5_000.0;
+        let (d_lat, d_lng) = center.bounds(radius);
+        let d_lng = d_lng.unwrap();
+
+        assert!(center.distance_to(&at(center.lat + d_lat, center.lng)) >= radius - 1e-6);
+        assert!(center.distance_to(&at(center.lat, center.lng + d_lng)) >= radius - 1e-6);
+        // longitude degrees shrink away from the equator
+        assert!(d_lng > d_lat);
+    }
+
+    #[test]
+    fn bounds_near_a_pole_leave_longitude_open() {
+        assert_eq!(at(89.99, 10.0).bounds(5_000.0).1, None);
+        assert_eq!(at(-90.0, 0.0).bounds(1.0).1, None);
+        assert!(at(80.0, 10.0).bounds(5_000.0).1.is_some());
+    }
 }
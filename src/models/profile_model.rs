@@ -1,8 +1,29 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+use super::user_model::Role;
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
 pub struct ProfileEntity {
     pub id: i32,
     pub name: String,
     pub email: String,
+    pub role: Role,
+    pub verified: bool,
+    pub two_factor: bool,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileQuery {
+    /// Lists deleted users as well, for those who manage users.
+    #[serde(default)]
+    pub include_deleted: bool,
+    /// Part of the name, in any case.
+    pub name: Option<String>,
+    /// Part of the email, in any case.
+    pub email: Option<String>,
+    pub role: Option<Role>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
 }
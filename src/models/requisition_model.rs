@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "requisition_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RequisitionStatus {
+    Draft,
+    Submitted,
+    Approved,
+    Rejected,
+    Fulfilled,
+    Cancelled,
+}
+
+impl RequisitionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Draft => "draft",
+            Self::Submitted => "submitted",
+            Self::Approved => "approved",
+            Self::Rejected => "rejected",
+            Self::Fulfilled => "fulfilled",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    /// The edges of the requisition state machine:
+    /// draft → submitted → approved/rejected → fulfilled, and anything not yet
+    /// decided or fulfilled can be cancelled.
+    pub fn can_transition_to(&self, next: RequisitionStatus) -> bool {
+        use RequisitionStatus::*;
+
+        matches!(
+            (self, next),
+            (Draft, Submitted)
+                | (Submitted, Approved)
+                | (Submitted, Rejected)
+                | (Approved, Fulfilled)
+                | (Draft | Submitted | Approved, Cancelled)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "requisition_line_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RequisitionLineStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl RequisitionLineStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Approved => "approved",
+            Self::Rejected => "rejected",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequisitionEntity {
+    pub id: i32,
+    pub user_id: i32,
+    pub status: RequisitionStatus,
+    pub note: Option<String>,
+    pub place_id: Option<i32>,
+    pub approver_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequisitionLineEntity {
+    pub id: i32,
+    pub requisition_id: i32,
+    pub item_id: i32,
+    pub quantity: i32,
+    pub status: RequisitionLineStatus,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RequisitionBody {
+    #[serde(flatten)]
+    pub requisition: RequisitionEntity,
+    pub lines: Vec<RequisitionLineEntity>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateRequisitionLineDTO {
+    pub item_id: i32,
+    #[validate(range(min = 1, message = "Must be positive"))]
+    pub quantity: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateRequisitionDTO {
+    #[validate(length(max = 255, message = "Must have at most 255 characters"))]
+    pub note: Option<String>,
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    #[validate]
+    pub lines: Vec<CreateRequisitionLineDTO>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct FulfilRequisitionDTO {
+    pub place_id: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequisitionQuery {
+    pub status: Option<RequisitionStatus>,
+}
@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    Place,
+    Item,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// Only search places or items.
+    pub kind: Option<SearchKind>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub kind: String,
+    pub id: i32,
+    pub name: String,
+    /// The name and description as escaped HTML, with the matched words
+    /// wrapped in `<mark>`.
+    pub snippet: String,
+    pub rank: f32,
+}
@@ -0,0 +1,142 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::validation::invalid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "movement_kind", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MovementKind {
+    Receipt,
+    Issue,
+    Adjustment,
+    Transfer,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockLevelEntity {
+    pub item_id: i32,
+    pub place_id: i32,
+    pub quantity: i32,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The quantity of an item summed over several places.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockTotal {
+    pub item_id: i32,
+    pub quantity: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockMovementEntity {
+    pub id: i32,
+    pub item_id: i32,
+    pub place_id: i32,
+    pub kind: MovementKind,
+    pub quantity: i32,
+    pub note: Option<String>,
+    pub user_id: Option<i32>,
+    pub transfer_id: Option<i32>,
+    pub loan_id: Option<i32>,
+    pub requisition_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A movement about to be written to the ledger, `quantity` is the signed delta.
+#[derive(Debug)]
+pub struct NewMovement {
+    pub item_id: i32,
+    pub place_id: i32,
+    pub kind: MovementKind,
+    pub quantity: i32,
+    pub note: Option<String>,
+    pub user_id: i32,
+    pub transfer_id: Option<i32>,
+    pub loan_id: Option<i32>,
+    pub requisition_id: Option<i32>,
+}
+
+/// A movement as sent by the client. For receipts and issues `quantity` is the
+/// amount moved and must be positive, for adjustments it is the signed delta.
+#[derive(Debug, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_movement", skip_on_field_errors = false))]
+pub struct CreateMovementDTO {
+    pub item_id: i32,
+    pub place_id: i32,
+    pub kind: MovementKind,
+    pub quantity: i32,
+    #[validate(length(max = 255, message = "Must have at most 255 characters"))]
+    pub note: Option<String>,
+}
+
+impl CreateMovementDTO {
+    pub fn into_movement(self, user_id: i32) -> NewMovement {
+        let quantity = match self.kind {
+            MovementKind::Issue => -self.quantity,
+            _ => self.quantity,
+        };
+
+        NewMovement {
+            item_id: self.item_id,
+            place_id: self.place_id,
+            kind: self.kind,
+            quantity,
+            note: self.note,
+            user_id,
+            transfer_id: None,
+            loan_id: None,
+            requisition_id: None,
+        }
+    }
+}
+
+fn validate_movement(data: &CreateMovementDTO) -> Result<(), ValidationError> {
+    match data.kind {
+        MovementKind::Transfer => Err(invalid("transfers must be made between two places")),
+        MovementKind::Adjustment if data.quantity == 0 => Err(invalid("quantity can not be zero")),
+        MovementKind::Receipt | MovementKind::Issue if data.quantity <= 0 => {
+            Err(invalid("quantity must be positive"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferEntity {
+    pub id: i32,
+    pub item_id: i32,
+    pub from_place_id: i32,
+    pub to_place_id: i32,
+    pub quantity: i32,
+    pub note: Option<String>,
+    pub user_id: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_transfer", skip_on_field_errors = false))]
+pub struct CreateTransferDTO {
+    pub item_id: i32,
+    pub from_place_id: i32,
+    pub to_place_id: i32,
+    #[validate(range(min = 1, message = "Must be positive"))]
+    pub quantity: i32,
+    #[validate(length(max = 255, message = "Must have at most 255 characters"))]
+    pub note: Option<String>,
+}
+
+fn validate_transfer(data: &CreateTransferDTO) -> Result<(), ValidationError> {
+    if data.from_place_id == data.to_place_id {
+        return Err(invalid("source and destination must be different places"));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MovementQuery {
+    pub item_id: Option<i32>,
+    pub place_id: Option<i32>,
+}
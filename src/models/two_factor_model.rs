@@ -0,0 +1,59 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use super::user_model::{Role, UserBody};
+
+#[derive(Serialize, Deserialize)]
+pub struct EnrolmentBody {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecoveryCodesBody {
+    pub recovery_codes: Vec<String>,
+}
+
+/// Returned by a password login when the account still has to pass its second factor.
+#[derive(Serialize, Deserialize)]
+pub struct TwoFactorChallengeBody {
+    pub two_factor_required: bool,
+    pub challenge: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LoginBody {
+    Session(UserBody),
+    TwoFactor(TwoFactorChallengeBody),
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct TwoFactorCodeDTO {
+    /// Either the current code of the authenticator or one of the recovery codes.
+    #[validate(length(min = 6, max = 32, message = "Invalid code"))]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct TwoFactorLoginDTO {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub challenge: String,
+    #[validate(length(min = 6, max = 32, message = "Invalid code"))]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RolePolicyEntity {
+    pub id: i32,
+    pub role: Role,
+    pub require_two_factor: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateRolePolicyDTO {
+    pub role: Role,
+    pub require_two_factor: bool,
+}
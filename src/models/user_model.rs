@@ -2,12 +2,26 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "user_role", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Storekeeper,
+    Requester,
+    Auditor,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserEntity {
     pub id: i32,
     pub name: String,
     pub email: String,
-    pub password: String,
+    /// `None` for accounts provisioned through single sign-on.
+    pub password: Option<String>,
+    pub role: Role,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -17,7 +31,11 @@ pub struct UserBody {
     pub id: i32,
     pub name: String,
     pub email: String,
+    pub role: Role,
+    pub verified: bool,
+    pub two_factor: bool,
     pub token: String,
+    pub refresh_token: String,
 }
 
 #[derive(Serialize, Deserialize, Validate)]
@@ -33,12 +51,25 @@ pub struct CreateUserDTO {
     pub name: String,
     #[validate(email(message = "Invalid email"))]
     pub email: String,
+    #[validate(custom = "crate::password::validate_password")]
     pub password: String,
 }
 
+/// A partial update, fields left out are kept as they are.
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct UpdateUserDTO {
+    #[serde(default, deserialize_with = "crate::validation::non_null")]
     #[validate(length(min = 1, message = "Can not be empty"))]
-    pub name: String,
-    pub password: String,
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "crate::validation::non_null")]
+    #[validate(custom = "crate::password::validate_password")]
+    pub password: Option<String>,
+    /// Needed to change the password of one's own account.
+    #[serde(default)]
+    pub current_password: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateRoleDTO {
+    pub role: Role,
 }
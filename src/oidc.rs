@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use tokio::sync::{OnceCell, RwLock};
+
+use crate::config::Config;
+
+/// The parts of the provider's discovery document we use.
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Claims of an ID token, once its signature, issuer, audience and nonce checked out.
+#[derive(Debug, Deserialize)]
+pub struct IdClaims {
+    pub iss: String,
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub preferred_username: Option<String>,
+    nonce: Option<String>,
+}
+
+/// An OpenID Connect provider users can log in with through the
+/// authorization code flow. Its discovery document is fetched on first use,
+/// so the provider doesn't have to be up when we start.
+pub struct OidcProvider {
+    issuer: String,
+    client_id: String,
+    client_secret: String,
+    redirect_url: String,
+    http: reqwest::Client,
+    metadata: OnceCell<Metadata>,
+    jwks: RwLock<JwkSet>,
+}
+
+pub fn from_config(cfg: &Config) -> anyhow::Result<Option<Arc<OidcProvider>>> {
+    let Some(issuer) = &cfg.oidc_issuer else {
+        return Ok(None);
+    };
+
+    Ok(Some(Arc::new(OidcProvider {
+        issuer: issuer.trim_end_matches('/').to_string(),
+        client_id: cfg
+            .oidc_client_id
+            .clone()
+            .context("oidc_client_id is required by oidc_issuer")?,
+        client_secret: cfg
+            .oidc_client_secret
+            .clone()
+            .context("oidc_client_secret is required by oidc_issuer")?,
+        redirect_url: cfg.oidc_redirect_url.clone(),
+        http: reqwest::Client::new(),
+        metadata: OnceCell::new(),
+        jwks: RwLock::new(JwkSet { keys: vec![] }),
+    })))
+}
+
+impl OidcProvider {
+    pub async fn authorization_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> anyhow::Result<String> {
+        let metadata = self.metadata().await?;
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.client_id),
+                ("redirect_uri", &self.redirect_url),
+                ("scope", "openid email profile"),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .context("invalid authorization_endpoint")?;
+
+        Ok(url.into())
+    }
+
+    /// Trades an authorization code for the user's ID token. `None` means the
+    /// provider turned the code down or the token didn't check out.
+    pub async fn exchange(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> anyhow::Result<Option<IdClaims>> {
+        let metadata = self.metadata().await?;
+
+        let response = self
+            .http
+            .post(&metadata.token_endpoint)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_url),
+                ("client_id", &self.client_id),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await
+            .context("could not reach token_endpoint")?;
+
+        if response.status() == StatusCode::BAD_REQUEST
+            || response.status() == StatusCode::UNAUTHORIZED
+        {
+            tracing::warn!("Provider rejected code: {}", response.text().await?);
+            return Ok(None);
+        }
+
+        let tokens: TokenResponse = response
+            .error_for_status()
+            .context("token_endpoint failed")?
+            .json()
+            .await
+            .context("invalid token response")?;
+
+        let claims = self.verify(&tokens.id_token, metadata).await?;
+
+        Ok(claims.filter(|claims| claims.nonce.as_deref() == Some(nonce)))
+    }
+
+    async fn verify(
+        &self,
+        id_token: &str,
+        metadata: &Metadata,
+    ) -> anyhow::Result<Option<IdClaims>> {
+        let Ok(header) = decode_header(id_token) else {
+            return Ok(None);
+        };
+        // tokens MACed with our client secret are allowed by the spec, but
+        // we only accept the provider's own signatures
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Ok(None);
+        }
+        let Some(key) = self.decoding_key(header.kid.as_deref(), metadata).await? else {
+            return Ok(None);
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.client_id]);
+
+        Ok(decode::<IdClaims>(id_token, &key, &validation)
+            .map_err(|e| tracing::warn!("Invalid ID token: {}", e))
+            .ok()
+            .map(|data| data.claims))
+    }
+
+    /// Finds the key named `kid`, fetching the provider's keys again when it
+    /// is unknown in case they were rotated.
+    async fn decoding_key(
+        &self,
+        kid: Option<&str>,
+        metadata: &Metadata,
+    ) -> anyhow::Result<Option<DecodingKey>> {
+        let find = |jwks: &JwkSet| {
+            jwks.keys
+                .iter()
+                .find(|jwk| kid.is_none() || jwk.common.key_id.as_deref() == kid)
+                .map(DecodingKey::from_jwk)
+                .transpose()
+        };
+
+        if let Some(key) = find(&*self.jwks.read().await)? {
+            return Ok(Some(key));
+        }
+
+        let jwks: JwkSet = self
+            .http
+            .get(&metadata.jwks_uri)
+            .send()
+            .await
+            .context("could not reach jwks_uri")?
+            .error_for_status()?
+            .json()
+            .await
+            .context("invalid JWKS")?;
+        let key = find(&jwks)?;
+        *self.jwks.write().await = jwks;
+
+        Ok(key)
+    }
+
+    async fn metadata(&self) -> anyhow::Result<&Metadata> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.issuer);
+                self.http
+                    .get(&url)
+                    .send()
+                    .await
+                    .context("could not reach the OpenID Connect provider")?
+                    .error_for_status()?
+                    .json::<Metadata>()
+                    .await
+                    .context("invalid discovery document")
+            })
+            .await
+    }
+}
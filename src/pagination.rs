@@ -0,0 +1,345 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::DateTime;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{postgres::PgRow, FromRow, Postgres, QueryBuilder};
+
+use crate::{
+    validation::{field_error, CustomError},
+    Result,
+};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 100;
+
+/// A column a list can be sorted on.
+pub struct SortKey {
+    /// Its name in `?sort=`, which is also the entity field holding its value.
+    pub name: &'static str,
+    pub column: &'static str,
+    /// What the value carried by the cursor is cast to.
+    pub sql_type: &'static str,
+}
+
+impl SortKey {
+    /// Whether a cursor value can be cast to the type of the column, as
+    /// cursors come back from clients and a failed cast fails the query.
+    fn accepts(&self, value: &str) -> bool {
+        match self.sql_type {
+            "INTEGER" => value.parse::<i32>().is_ok(),
+            "BIGINT" => value.parse::<i64>().is_ok(),
+            "TIMESTAMPTZ" => DateTime::parse_from_rfc3339(value).is_ok(),
+            _ => !value.contains('\0'),
+        }
+    }
+}
+
+/// The filters of a list endpoint, on top of the paging every list has.
+pub trait ListFilter: DeserializeOwned {
+    /// What the list can be sorted on, the first one is the default.
+    const SORT_KEYS: &'static [SortKey];
+
+    /// Appends the conditions of the filter, each one starting with ` AND `.
+    fn push_conditions(&self, query: &mut QueryBuilder<'_, Postgres>);
+}
+
+#[derive(Debug, Deserialize)]
+struct PageParams {
+    limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+}
+
+/// Where the previous page ended, handed to clients as an opaque string.
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    sort: String,
+    value: String,
+    id: i32,
+}
+
+/// Paging, sorting and filters of a list, read from a query string such as
+/// `?limit=20&sort=-created_at&cursor=...` plus the fields of `F`. Sorting on
+/// a field descending is asked for by prefixing it with `-`.
+pub struct ListQuery<F> {
+    pub filter: F,
+    limit: i64,
+    sort: String,
+    key: &'static SortKey,
+    descending: bool,
+    after: Option<Cursor>,
+}
+
+/// One page of a list, `next_cursor` is `None` on the last one.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    /// How many rows match the filters across all pages.
+    pub total: i64,
+}
+
+#[async_trait]
+impl<S, F> FromRequestParts<S> for ListQuery<F>
+where
+    S: Send + Sync,
+    F: ListFilter,
+{
+    type Rejection = CustomError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<PageParams>::from_request_parts(parts, state).await?;
+        let Query(filter) = Query::<F>::from_request_parts(parts, state).await?;
+
+        let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(field_error("limit", "must be between 1 and 100"));
+        }
+
+        let sort = params
+            .sort
+            .unwrap_or_else(|| F::SORT_KEYS[0].name.to_string());
+        let (descending, name) = match sort.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, sort.as_str()),
+        };
+        let key = F::SORT_KEYS
+            .iter()
+            .find(|key| key.name == name)
+            .ok_or_else(|| field_error("sort", "can not sort on that field"))?;
+
+        let after = params
+            .cursor
+            .map(|cursor| {
+                URL_SAFE_NO_PAD
+                    .decode(cursor)
+                    .ok()
+                    .and_then(|json| serde_json::from_slice::<Cursor>(&json).ok())
+                    // a cursor only makes sense in the order it was made for
+                    .filter(|cursor| cursor.sort == sort && key.accepts(&cursor.value))
+                    .ok_or_else(|| field_error("cursor", "invalid cursor"))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            filter,
+            limit,
+            sort,
+            key,
+            descending,
+            after,
+        })
+    }
+}
+
+impl<F: ListFilter> ListQuery<F> {
+    /// Fetches the page of `SELECT columns FROM table` the query asks for.
+    pub async fn fetch<T>(
+        &self,
+        db: &sqlx::Pool<sqlx::Postgres>,
+        columns: &str,
+        table: &str,
+    ) -> Result<Page<T>>
+    where
+        T: for<'r> FromRow<'r, PgRow> + Serialize + Send + Unpin,
+    {
+        let mut count = QueryBuilder::new(format!("SELECT COUNT(*) FROM {} WHERE TRUE", table));
+        self.filter.push_conditions(&mut count);
+        let (total,): (i64,) = count.build_query_as().fetch_one(db).await?;
+
+        let (operator, order) = if self.descending {
+            ("<", "DESC")
+        } else {
+            (">", "ASC")
+        };
+
+        let mut query = QueryBuilder::new(format!("SELECT {} FROM {} WHERE TRUE", columns, table));
+        self.filter.push_conditions(&mut query);
+        if let Some(cursor) = &self.after {
+            // ties on the sorted column are broken by the id
+            query
+                .push(format!(" AND ({}, id) {} (", self.key.column, operator))
+                .push_bind(cursor.value.clone())
+                .push(format!("::{}, ", self.key.sql_type))
+                .push_bind(cursor.id)
+                .push(")");
+        }
+        query
+            .push(format!(
+                " ORDER BY {} {}, id {} LIMIT ",
+                self.key.column, order, order
+            ))
+            .push_bind(self.limit + 1);
+
+        let mut items: Vec<T> = query.build_query_as().fetch_all(db).await?;
+
+        let next_cursor = if items.len() as i64 > self.limit {
+            items.truncate(self.limit as usize);
+            items
+                .last()
+                .map(|last| self.cursor_after(last))
+                .transpose()?
+        } else {
+            None
+        };
+
+        Ok(Page {
+            items,
+            next_cursor,
+            total,
+        })
+    }
+
+    fn cursor_after<T: Serialize>(&self, item: &T) -> Result<String> {
+        let item = serde_json::to_value(item).map_err(anyhow::Error::from)?;
+
+        let value = match &item[self.key.name] {
+            Value::String(value) => value.clone(),
+            Value::Null => return Err(anyhow::anyhow!("{} is null", self.key.name).into()),
+            value => value.to_string(),
+        };
+        let id = item["id"]
+            .as_i64()
+            .and_then(|id| i32::try_from(id).ok())
+            .ok_or_else(|| anyhow::anyhow!("list entities must have an id"))?;
+
+        let cursor = serde_json::to_vec(&Cursor {
+            sort: self.sort.clone(),
+            value,
+            id,
+        })
+        .map_err(anyhow::Error::from)?;
+
+        Ok(URL_SAFE_NO_PAD.encode(cursor))
+    }
+}
+
+/// A pattern for `ILIKE` matching values that contain `text`.
+pub fn contains_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("%{}%", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct NoFilter {}
+
+    impl ListFilter for NoFilter {
+        const SORT_KEYS: &'static [SortKey] = &[
+            SortKey {
+                name: "id",
+                column: "id",
+                sql_type: "INTEGER",
+            },
+            SortKey {
+                name: "name",
+                column: "name",
+                sql_type: "TEXT",
+            },
+        ];
+
+        fn push_conditions(&self, _query: &mut QueryBuilder<'_, Postgres>) {}
+    }
+
+    async fn list_query(query: &str) -> Result<ListQuery<NoFilter>> {
+        let (mut parts, _) = Request::builder()
+            .uri(format!("/?{}", query))
+            .body(())
+            .unwrap()
+            .into_parts();
+
+        ListQuery::from_request_parts(&mut parts, &()).await
+    }
+
+    #[tokio::test]
+    async fn cursor_round_trips() {
+        let query = list_query("sort=-name").await.unwrap();
+        let cursor = query
+            .cursor_after(&json!({ "id": 7, "name": "Shelf" }))
+            .unwrap();
+
+        let next = list_query(&format!("sort=-name&cursor={}", cursor))
+            .await
+            .unwrap();
+        let after = next.after.unwrap();
+        assert_eq!(after.value, "Shelf");
+        assert_eq!(after.id, 7);
+        assert!(next.descending);
+        assert_eq!(next.key.name, "name");
+    }
+
+    #[tokio::test]
+    async fn cursor_is_refused_under_another_sort() {
+        let query = list_query("sort=name").await.unwrap();
+        let cursor = query
+            .cursor_after(&json!({ "id": 7, "name": "Shelf" }))
+            .unwrap();
+
+        for sort in ["-name", "id"] {
+            let result = list_query(&format!("sort={}&cursor={}", sort, cursor)).await;
+            assert!(matches!(result, Err(CustomError::ValidationError(_))));
+        }
+        assert!(list_query("cursor=garbage").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn cursor_value_must_fit_the_sort_key() {
+        for (sort, value) in [("id", "x"), ("id", "1.5"), ("-id", "99999999999")] {
+            let cursor = URL_SAFE_NO_PAD.encode(
+                serde_json::to_vec(&json!({ "sort": sort, "value": value, "id": 7 })).unwrap(),
+            );
+            let result = list_query(&format!("sort={}&cursor={}", sort, cursor)).await;
+            assert!(matches!(result, Err(CustomError::ValidationError(_))));
+        }
+
+        let created_at = SortKey {
+            name: "created_at",
+            column: "created_at",
+            sql_type: "TIMESTAMPTZ",
+        };
+        assert!(created_at.accepts("2024-03-01T12:30:00.123456Z"));
+        assert!(created_at.accepts("2024-03-01T12:30:00+02:00"));
+        assert!(!created_at.accepts("x"));
+        assert!(!created_at.accepts("yesterday"));
+    }
+
+    #[tokio::test]
+    async fn limit_is_bounded() {
+        assert_eq!(list_query("").await.unwrap().limit, DEFAULT_LIMIT);
+        assert_eq!(list_query("limit=1").await.unwrap().limit, 1);
+        assert_eq!(list_query("limit=100").await.unwrap().limit, MAX_LIMIT);
+        assert!(list_query("limit=0").await.is_err());
+        assert!(list_query("limit=101").await.is_err());
+        assert!(list_query("limit=-1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unknown_sort_is_refused() {
+        assert_eq!(list_query("").await.unwrap().key.name, "id");
+        assert!(list_query("sort=password").await.is_err());
+    }
+
+    #[test]
+    fn contains_pattern_escapes_wildcards() {
+        assert_eq!(contains_pattern("abc"), "%abc%");
+        assert_eq!(contains_pattern("50%"), "%50\\%%");
+        assert_eq!(contains_pattern("a_b"), "%a\\_b%");
+        assert_eq!(contains_pattern("c:\\dir"), "%c:\\\\dir%");
+        assert_eq!(contains_pattern("\\%"), "%\\\\\\%%");
+    }
+}
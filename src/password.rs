@@ -0,0 +1,118 @@
+use std::{borrow::Cow, collections::HashSet, sync::OnceLock};
+
+use anyhow::Context;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, Version};
+use validator::ValidationError;
+
+use crate::{config::Config, validation::field_error};
+
+/// Longest password we hash, so huge inputs can't tie up the hasher.
+const MAX_LENGTH: usize = 128;
+
+/// What passwords must look like and how they are hashed. It is read from
+/// validators, which have no access to the app state, hence the global.
+pub struct PasswordPolicy {
+    min_length: usize,
+    common_passwords: HashSet<String>,
+    params: Params,
+}
+
+static POLICY: OnceLock<PasswordPolicy> = OnceLock::new();
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            common_passwords: HashSet::new(),
+            params: Params::default(),
+        }
+    }
+}
+
+/// Loads the policy from `cfg`, to be called once before serving requests.
+pub fn init(cfg: &Config) -> anyhow::Result<()> {
+    let common_passwords = match &cfg.common_passwords_file {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?
+            .lines()
+            .map(|line| line.trim().to_lowercase())
+            .filter(|line| !line.is_empty())
+            .collect(),
+        None => HashSet::new(),
+    };
+
+    let params = Params::new(
+        cfg.argon2_memory_kib,
+        cfg.argon2_iterations,
+        cfg.argon2_parallelism,
+        None,
+    )
+    .map_err(|e| anyhow::anyhow!("invalid argon2 parameters: {}", e))?;
+
+    POLICY
+        .set(PasswordPolicy {
+            min_length: cfg.password_min_length,
+            common_passwords,
+            params,
+        })
+        .map_err(|_| anyhow::anyhow!("password policy already initialized"))
+}
+
+fn policy() -> &'static PasswordPolicy {
+    POLICY.get_or_init(PasswordPolicy::default)
+}
+
+pub fn hasher() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, policy().params.clone())
+}
+
+/// Whether `hash` was made with other parameters than the current ones.
+pub fn needs_rehash(hash: &PasswordHash) -> bool {
+    let current = &policy().params;
+
+    hash.algorithm != Algorithm::Argon2id.ident()
+        || hash.version != Some(Version::V0x13.into())
+        || Params::try_from(hash).map_or(true, |params| {
+            params.m_cost() != current.m_cost()
+                || params.t_cost() != current.t_cost()
+                || params.p_cost() != current.p_cost()
+        })
+}
+
+pub fn validate_password(password: &str) -> Result<(), ValidationError> {
+    let policy = policy();
+    let length = password.chars().count();
+
+    if length < policy.min_length {
+        return Err(error(format!(
+            "Must have at least {} characters",
+            policy.min_length
+        )));
+    }
+    if length > MAX_LENGTH {
+        return Err(error(format!(
+            "Must have at most {} characters",
+            MAX_LENGTH
+        )));
+    }
+    if policy.common_passwords.contains(&password.to_lowercase()) {
+        return Err(error("Too common, choose another one".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Kept out of the validators since not every request carries the email.
+pub fn ensure_not_email(password: &str, email: &str) -> crate::Result<()> {
+    if password.trim().eq_ignore_ascii_case(email.trim()) {
+        return Err(field_error("password", "can not be your email"));
+    }
+
+    Ok(())
+}
+
+fn error(message: String) -> ValidationError {
+    let mut error = ValidationError::new("password");
+    error.message = Some(Cow::Owned(message));
+    error
+}
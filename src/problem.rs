@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+tokio::task_local! {
+    /// The `x-request-id` of the request being handled.
+    static REQUEST_ID: Option<String>;
+}
+
+/// Makes the `x-request-id` of a request known to the errors it ends with.
+/// Has to run inside the layer that sets it.
+pub async fn scope_request_id<B>(req: Request<B>, next: Next<B>) -> Response {
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    REQUEST_ID.scope(request_id, next.run(req)).await
+}
+
+/// The `x-request-id` of the request being handled, if any.
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok().flatten()
+}
+
+/// The body of an error response, as described by RFC 7807 and served as
+/// `application/problem+json`.
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: &'static str,
+    status: u16,
+    /// Tells errors apart for programs, it doesn't change along with `detail`.
+    code: &'static str,
+    detail: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    /// Members particular to the error, such as `retry_after`.
+    #[serde(flatten)]
+    extensions: Map<String, Value>,
+}
+
+/// What is wrong with one field of the input.
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    /// Where the field is, such as `coordinates.lat` or `lines[0].quantity`.
+    field: String,
+    /// Which check failed, such as `length`, `range` or `unique`.
+    code: String,
+    message: String,
+    /// The database constraint that refused the input.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    constraint: Option<String>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    params: Map<String, Value>,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, code: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            kind: "about:blank",
+            title: status.canonical_reason().unwrap_or_default(),
+            status: status.as_u16(),
+            code,
+            detail: detail.into(),
+            errors: vec![],
+            request_id: request_id(),
+            extensions: Map::new(),
+        }
+    }
+
+    /// Adds a member particular to this kind of error.
+    pub fn with(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.extensions.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Lists every field error of `errors`, nested ones included.
+    pub fn with_field_errors(mut self, errors: &ValidationErrors) -> Self {
+        flatten(errors, "", &mut self.errors);
+        self.errors.sort_by(|a, b| a.field.cmp(&b.field));
+        self
+    }
+}
+
+fn flatten(errors: &ValidationErrors, prefix: &str, out: &mut Vec<FieldError>) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                out.extend(errors.iter().map(|error| {
+                    // `value` is left out as it echoes the input, passwords included
+                    let mut params: BTreeMap<_, _> = error
+                        .params
+                        .iter()
+                        .filter(|(name, _)| *name != "value")
+                        .map(|(name, value)| (name.to_string(), value.clone()))
+                        .collect();
+                    let constraint = params
+                        .remove("constraint")
+                        .and_then(|value| value.as_str().map(str::to_string));
+
+                    FieldError {
+                        field: path.clone(),
+                        code: error.code.to_string(),
+                        message: error.message.as_deref().unwrap_or(&error.code).to_string(),
+                        constraint,
+                        params: params.into_iter().collect(),
+                    }
+                }))
+            }
+            ValidationErrorsKind::Struct(errors) => flatten(errors, &path, out),
+            ValidationErrorsKind::List(errors) => {
+                for (index, errors) in errors {
+                    flatten(errors, &format!("{}[{}]", path, index), out);
+                }
+            }
+        }
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        match serde_json::to_vec(&self) {
+            Ok(body) => {
+                (status, [(CONTENT_TYPE, "application/problem+json")], body).into_response()
+            }
+            Err(e) => {
+                tracing::error!("Could not serialize problem: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
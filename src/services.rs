@@ -1,3 +1,16 @@
+pub mod account_service;
+pub mod api_key_service;
+pub mod audit_service;
+pub mod image_service;
+pub mod item_service;
+pub mod loan_service;
+pub mod lockout_service;
+pub mod oidc_service;
 pub mod place_service;
 pub mod profile_service;
+pub mod requisition_service;
+pub mod search_service;
+pub mod session_service;
+pub mod stock_service;
+pub mod two_factor_service;
 pub mod user_service;
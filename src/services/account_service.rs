@@ -0,0 +1,201 @@
+use chrono::{Duration, Utc};
+
+use crate::{
+    authorization::{ActionClaims, Actor},
+    mailer::Mail,
+    models::{
+        account_model::{ResetPasswordDTO, TokenPurpose},
+        profile_model::ProfileEntity,
+        user_model::Role,
+    },
+    password,
+    services::{session_service, user_service},
+    validation::field_error,
+    AppState, Result,
+};
+
+const VERIFICATION_TTL_HOURS: i64 = 48;
+const PASSWORD_RESET_TTL_HOURS: i64 = 1;
+
+/// Mails `email` a link that confirms the address belongs to `user_id`.
+pub async fn send_verification(state: &AppState, user_id: i32, email: &str) -> Result<()> {
+    let token = issue_token(
+        state,
+        user_id,
+        TokenPurpose::EmailVerification,
+        Duration::hours(VERIFICATION_TTL_HOURS),
+    )
+    .await?;
+
+    state
+        .mailer
+        .send(Mail {
+            to: email.to_string(),
+            subject: "Confirm your email address".to_string(),
+            body: format!(
+                "Open the link below to confirm your email address:\n\n{}/verify-email?token={}\n\n\
+                The link expires in {} hours.",
+                state.config.app_url, token, VERIFICATION_TTL_HOURS
+            ),
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn verify_email(state: &AppState, token: &str, actor: &Actor) -> Result<()> {
+    let user_id = use_token(state, token, TokenPurpose::EmailVerification).await?;
+
+    let mut tx = state.db.begin().await?;
+    let before = user_service::lock_profile(&mut tx, user_id)
+        .await?
+        .ok_or_else(|| field_error("token", "invalid or expired token"))?;
+
+    let after = sqlx::query_as!(
+        ProfileEntity,
+        r#"UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1
+        RETURNING id, name, email, role as "role: Role", email_verified_at IS NOT NULL as "verified!",
+        totp_enabled_at IS NOT NULL as "two_factor!", deleted_at"#,
+        user_id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    // whoever holds the link acts as its owner
+    let actor = Actor {
+        user_id: Some(user_id),
+        ..actor.clone()
+    };
+    user_service::record_update(&mut tx, &actor, &before, &after, false).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Mails a password reset link if `email` belongs to someone. Callers are not
+/// told whether it did, so this can't be used to find out who has an account.
+pub async fn request_password_reset(state: &AppState, email: &str) -> Result<()> {
+    let user_id = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE email = $1 AND deleted_at IS NULL",
+        email
+    )
+    .fetch_optional(&state.db)
+    .await?;
+
+    let Some(user_id) = user_id else {
+        return Ok(());
+    };
+
+    let token = issue_token(
+        state,
+        user_id,
+        TokenPurpose::PasswordReset,
+        Duration::hours(PASSWORD_RESET_TTL_HOURS),
+    )
+    .await?;
+
+    state
+        .mailer
+        .send(Mail {
+            to: email.to_string(),
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Someone asked to reset the password of your account. If it was you, open the link \
+                below to choose a new one:\n\n{}/reset-password?token={}\n\n\
+                The link expires in {} hour. If it wasn't you, you can ignore this mail.",
+                state.config.app_url, token, PASSWORD_RESET_TTL_HOURS
+            ),
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Sets a new password and logs the account out everywhere.
+pub async fn reset_password(state: &AppState, data: ResetPasswordDTO, actor: &Actor) -> Result<()> {
+    // checked before the token is burnt, so a refused password can be retried
+    if let Some(claims) =
+        ActionClaims::from_jwt(&data.token, TokenPurpose::PasswordReset, &state.config)
+    {
+        let email = sqlx::query_scalar!("SELECT email FROM users WHERE id = $1", claims.sub)
+            .fetch_optional(&state.db)
+            .await?;
+        if let Some(email) = email {
+            password::ensure_not_email(&data.password, &email)?;
+        }
+    }
+
+    let user_id = use_token(state, &data.token, TokenPurpose::PasswordReset).await?;
+    let pass_hash = user_service::hash_password(data.password).await?;
+
+    let mut tx = state.db.begin().await?;
+    let before = user_service::lock_profile(&mut tx, user_id)
+        .await?
+        .ok_or_else(|| field_error("token", "invalid or expired token"))?;
+
+    // following the link proves the address too
+    let after = sqlx::query_as!(
+        ProfileEntity,
+        r#"UPDATE users SET password = $2, email_verified_at = COALESCE(email_verified_at, NOW())
+        WHERE id = $1
+        RETURNING id, name, email, role as "role: Role", email_verified_at IS NOT NULL as "verified!",
+        totp_enabled_at IS NOT NULL as "two_factor!", deleted_at"#,
+        user_id,
+        pass_hash
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    // whoever holds the link acts as its owner
+    let actor = Actor {
+        user_id: Some(user_id),
+        ..actor.clone()
+    };
+    user_service::record_update(&mut tx, &actor, &before, &after, true).await?;
+    tx.commit().await?;
+
+    session_service::revoke_all_sessions(&state.db, user_id).await?;
+
+    Ok(())
+}
+
+pub(crate) async fn issue_token(
+    state: &AppState,
+    user_id: i32,
+    purpose: TokenPurpose,
+    ttl: Duration,
+) -> Result<String> {
+    let expires_at = Utc::now() + ttl;
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO action_tokens (user_id, purpose, expires_at) VALUES ($1, $2, $3) RETURNING id",
+        user_id,
+        purpose as TokenPurpose,
+        expires_at
+    )
+    .fetch_one(&state.db)
+    .await?;
+
+    ActionClaims::new(user_id, id, purpose, expires_at).to_jwt(&state.config)
+}
+
+/// Checks `token` and burns it, returning the user it was issued to.
+pub(crate) async fn use_token(state: &AppState, token: &str, purpose: TokenPurpose) -> Result<i32> {
+    let invalid = || field_error("token", "invalid or expired token");
+
+    let claims = ActionClaims::from_jwt(token, purpose, &state.config).ok_or_else(invalid)?;
+
+    let user_id = sqlx::query_scalar!(
+        "UPDATE action_tokens SET used_at = NOW()
+        WHERE id = $1 AND user_id = $2 AND purpose = $3 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id",
+        claims.jti,
+        claims.sub,
+        purpose as TokenPurpose
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(invalid)?;
+
+    Ok(user_id)
+}
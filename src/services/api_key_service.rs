@@ -0,0 +1,198 @@
+use chrono::{Duration, Utc};
+
+use crate::{
+    authorization::{Actor, Permission},
+    models::{
+        api_key_model::{ApiKeyBody, ApiKeyEntity, CreateApiKeyDTO},
+        audit_model::{AuditAction, AuditEntityType},
+        user_model::Role,
+    },
+    services::{audit_service, session_service},
+    validation::field_error,
+    Result,
+};
+
+const KEY_PREFIX: &str = "almx_";
+
+/// Whoever a key belongs to, as read when the key is presented.
+pub struct ApiKeyOwner {
+    pub user_id: i32,
+    pub role: Role,
+    pub verified: bool,
+    pub two_factor_missing: bool,
+    pub scopes: Vec<Permission>,
+}
+
+/// Creates a key for `user_id`. Only its hash is stored, so the key in the
+/// returned body can't be shown again.
+pub async fn create_api_key(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+    role: Role,
+    data: CreateApiKeyDTO,
+    actor: &Actor,
+) -> Result<ApiKeyBody> {
+    if data.scopes.iter().any(|&scope| !role.can(scope)) {
+        return Err(field_error("scopes", "not granted by your role"));
+    }
+    if data.expires_at.is_some_and(|at| at <= Utc::now()) {
+        return Err(field_error("expires_at", "must be in the future"));
+    }
+
+    let key = format!("{}{}", KEY_PREFIX, session_service::generate_token());
+    let mut tx = db.begin().await?;
+
+    let api_key = sqlx::query_as!(
+        ApiKeyEntity,
+        r#"INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, user_id, name, prefix, scopes as "scopes: Vec<Permission>",
+            last_used_at, expires_at, revoked_at, created_at"#,
+        user_id,
+        data.name,
+        &key[..KEY_PREFIX.len() + 8],
+        session_service::hash_token(&key),
+        &data.scopes as &[Permission],
+        data.expires_at
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Create,
+        AuditEntityType::ApiKey,
+        api_key.id,
+        None,
+        Some(&api_key),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(ApiKeyBody { api_key, key })
+}
+
+pub async fn get_api_keys(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+) -> Result<Vec<ApiKeyEntity>> {
+    let api_keys = sqlx::query_as!(
+        ApiKeyEntity,
+        r#"SELECT id, user_id, name, prefix, scopes as "scopes: Vec<Permission>",
+            last_used_at, expires_at, revoked_at, created_at
+        FROM api_keys WHERE user_id = $1 ORDER BY created_at DESC"#,
+        user_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(api_keys)
+}
+
+pub async fn get_api_key(db: &sqlx::Pool<sqlx::Postgres>, id: i32) -> Result<Option<ApiKeyEntity>> {
+    let api_key = sqlx::query_as!(
+        ApiKeyEntity,
+        r#"SELECT id, user_id, name, prefix, scopes as "scopes: Vec<Permission>",
+            last_used_at, expires_at, revoked_at, created_at
+        FROM api_keys WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(api_key)
+}
+
+pub async fn revoke_api_key(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+    actor: &Actor,
+) -> Result<Option<ApiKeyEntity>> {
+    let mut tx = db.begin().await?;
+
+    let Some(before) = sqlx::query_as!(
+        ApiKeyEntity,
+        r#"SELECT id, user_id, name, prefix, scopes as "scopes: Vec<Permission>",
+            last_used_at, expires_at, revoked_at, created_at
+        FROM api_keys WHERE id = $1 FOR UPDATE"#,
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let api_key = sqlx::query_as!(
+        ApiKeyEntity,
+        r#"UPDATE api_keys SET revoked_at = COALESCE(revoked_at, NOW()) WHERE id = $1
+        RETURNING id, user_id, name, prefix, scopes as "scopes: Vec<Permission>",
+            last_used_at, expires_at, revoked_at, created_at"#,
+        id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::ApiKey,
+        api_key.id,
+        Some(&before),
+        Some(&api_key),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(api_key))
+}
+
+/// Looks up the owner of a live key. The last use is recorded at most once a
+/// minute, so scanners polling the API don't write on every request.
+pub async fn authenticate(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    key: &str,
+) -> Result<Option<ApiKeyOwner>> {
+    let found = sqlx::query!(
+        r#"SELECT k.id, k.user_id, k.scopes as "scopes: Vec<Permission>", k.last_used_at,
+            u.role as "role: Role",
+            u.email_verified_at IS NOT NULL as "verified!",
+            u.totp_enabled_at IS NULL AND COALESCE(p.require_two_factor, FALSE) as "two_factor_missing!"
+        FROM api_keys k
+        JOIN users u ON u.id = k.user_id
+        LEFT JOIN role_policies p ON p.role = u.role
+        WHERE k.key_hash = $1 AND k.revoked_at IS NULL AND u.deleted_at IS NULL
+            AND (k.expires_at IS NULL OR k.expires_at > NOW())"#,
+        session_service::hash_token(key)
+    )
+    .fetch_optional(db)
+    .await?;
+
+    let Some(found) = found else {
+        return Ok(None);
+    };
+
+    if found
+        .last_used_at
+        .is_none_or(|at| at < Utc::now() - Duration::minutes(1))
+    {
+        sqlx::query!(
+            "UPDATE api_keys SET last_used_at = NOW() WHERE id = $1",
+            found.id
+        )
+        .execute(db)
+        .await?;
+    }
+
+    Ok(Some(ApiKeyOwner {
+        user_id: found.user_id,
+        role: found.role,
+        verified: found.verified,
+        two_factor_missing: found.two_factor_missing,
+        scopes: found.scopes,
+    }))
+}
@@ -0,0 +1,139 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::{Postgres, Transaction};
+
+use crate::{
+    authorization::Actor,
+    models::audit_model::{AuditAction, AuditEntity, AuditEntityType, AuditQuery},
+    Result,
+};
+
+const MAX_LIMIT: i64 = 100;
+
+/// Fields that change on every write and would only clutter the diff.
+const IGNORED_FIELDS: [&str; 1] = ["updated_at"];
+
+pub async fn get_entries(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    query: AuditQuery,
+) -> Result<Vec<AuditEntity>> {
+    let entries = sqlx::query_as!(
+        AuditEntity,
+        r#"SELECT id, actor_id, action as "action: AuditAction", entity_type as "entity_type: AuditEntityType", entity_id, before, after, request_id, ip, created_at
+        FROM audit_log
+        WHERE ($1::INTEGER IS NULL OR actor_id = $1)
+        AND ($2::audit_action IS NULL OR action = $2)
+        AND ($3::audit_entity IS NULL OR entity_type = $3)
+        AND ($4::INTEGER IS NULL OR entity_id = $4)
+        AND ($5::TIMESTAMPTZ IS NULL OR created_at >= $5)
+        AND ($6::TIMESTAMPTZ IS NULL OR created_at < $6)
+        ORDER BY id DESC
+        LIMIT $7"#,
+        query.actor_id,
+        query.action as Option<AuditAction>,
+        query.entity_type as Option<AuditEntityType>,
+        query.entity_id,
+        query.from,
+        query.to,
+        query.limit.unwrap_or(MAX_LIMIT).clamp(1, MAX_LIMIT)
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(entries)
+}
+
+pub async fn get_entry(db: &sqlx::Pool<sqlx::Postgres>, id: i32) -> Result<Option<AuditEntity>> {
+    let entry = sqlx::query_as!(
+        AuditEntity,
+        r#"SELECT id, actor_id, action as "action: AuditAction", entity_type as "entity_type: AuditEntityType", entity_id, before, after, request_id, ip, created_at
+        FROM audit_log WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(entry)
+}
+
+/// Records a mutation in the same transaction that made it, so there is no
+/// change without its entry. `before` is `None` for creates and `after` for
+/// deletes; on updates only the fields that changed are kept, and nothing is
+/// recorded when none did.
+pub(crate) async fn record<T: Serialize>(
+    tx: &mut Transaction<'_, Postgres>,
+    actor: &Actor,
+    action: AuditAction,
+    entity_type: AuditEntityType,
+    entity_id: i32,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<()> {
+    let before = before
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(anyhow::Error::from)?;
+    let after = after
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(anyhow::Error::from)?;
+
+    record_json(tx, actor, action, entity_type, entity_id, before, after).await
+}
+
+/// Same as [`record`] for snapshots that were already turned into JSON,
+/// for instance to add fields the entity doesn't expose.
+pub(crate) async fn record_json(
+    tx: &mut Transaction<'_, Postgres>,
+    actor: &Actor,
+    action: AuditAction,
+    entity_type: AuditEntityType,
+    entity_id: i32,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<()> {
+    let (before, after) = match (before, after) {
+        (Some(Value::Object(before)), Some(Value::Object(after))) => {
+            let (before, after) = diff(before, after);
+            if after.is_empty() && before.is_empty() {
+                return Ok(());
+            }
+            (Some(Value::Object(before)), Some(Value::Object(after)))
+        }
+        other => other,
+    };
+
+    sqlx::query!(
+        "INSERT INTO audit_log (actor_id, action, entity_type, entity_id, before, after, request_id, ip)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        actor.user_id,
+        action as AuditAction,
+        entity_type as AuditEntityType,
+        entity_id,
+        before,
+        after,
+        actor.request_id,
+        actor.ip
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Keeps the fields whose value differs between both snapshots.
+fn diff(
+    mut before: Map<String, Value>,
+    mut after: Map<String, Value>,
+) -> (Map<String, Value>, Map<String, Value>) {
+    let keys: Vec<String> = before.keys().chain(after.keys()).cloned().collect();
+
+    for key in keys {
+        if IGNORED_FIELDS.contains(&key.as_str()) || before.get(&key) == after.get(&key) {
+            before.remove(&key);
+            after.remove(&key);
+        }
+    }
+
+    (before, after)
+}
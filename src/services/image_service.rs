@@ -0,0 +1,177 @@
+use std::io::Cursor;
+
+use image::{
+    io::{Limits, Reader},
+    DynamicImage, ImageError, ImageFormat,
+};
+
+use crate::{
+    storage::Storage,
+    validation::{field_error, CustomError},
+    Result,
+};
+
+/// The longest side of a thumbnail, in pixels.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Wider or taller images are refused before being decoded, as they would
+/// take too much memory.
+const MAX_DIMENSION: u32 = 8192;
+
+/// Stores an uploaded image along with its thumbnail, returning the name
+/// both are served under. Names are never reused, so what is served under
+/// one never changes.
+pub async fn store_image(
+    storage: &dyn Storage,
+    content_type: &str,
+    data: Vec<u8>,
+) -> Result<String> {
+    let format = match content_type {
+        "image/jpeg" => ImageFormat::Jpeg,
+        "image/png" => ImageFormat::Png,
+        _ => return Err(CustomError::UnsupportedMediaType),
+    };
+    // the declared type is only a hint, what counts is what the bytes are
+    if image::guess_format(&data).ok() != Some(format) {
+        return Err(CustomError::UnsupportedMediaType);
+    }
+
+    let (data, thumbnail) = tokio::task::spawn_blocking(move || {
+        let thumbnail = make_thumbnail(&data, format)?;
+        Ok::<_, CustomError>((data, thumbnail))
+    })
+    .await
+    .map_err(anyhow::Error::from)??;
+
+    let name = format!(
+        "{}.{}",
+        hex::encode(rand::random::<[u8; 16]>()),
+        format.extensions_str()[0]
+    );
+    storage.put(&image_key(&name), content_type, data).await?;
+    storage
+        .put(&thumbnail_key(&name), content_type, thumbnail)
+        .await?;
+
+    Ok(name)
+}
+
+fn make_thumbnail(data: &[u8], format: ImageFormat) -> Result<Vec<u8>> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+
+    let mut reader = Reader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+    let image = reader.decode().map_err(|e| match e {
+        ImageError::Limits(_) => field_error("image", "image is too large"),
+        _ => field_error("image", "not a valid image"),
+    })?;
+
+    let thumbnail = if image.width() > THUMBNAIL_SIZE || image.height() > THUMBNAIL_SIZE {
+        image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+    } else {
+        image
+    };
+    let thumbnail = match format {
+        // JPEG has no transparency
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(thumbnail.to_rgb8()),
+        _ => thumbnail,
+    };
+
+    let mut out = Cursor::new(vec![]);
+    thumbnail
+        .write_to(&mut out, format)
+        .map_err(anyhow::Error::from)?;
+
+    Ok(out.into_inner())
+}
+
+/// An image or its thumbnail with its content type, `None` if there is no
+/// image of that name.
+pub async fn get_image(
+    storage: &dyn Storage,
+    name: &str,
+    thumbnail: bool,
+) -> Result<Option<(Vec<u8>, &'static str)>> {
+    let Some(content_type) = content_type(name) else {
+        return Ok(None);
+    };
+    let key = if thumbnail {
+        thumbnail_key(name)
+    } else {
+        image_key(name)
+    };
+
+    let data = storage.get(&key).await?;
+
+    Ok(data.map(|data| (data, content_type)))
+}
+
+/// Deletes an image that is no longer used. Failing to is only logged, the
+/// worst that can happen is an orphaned file.
+pub async fn release_image(storage: &dyn Storage, name: Option<String>) {
+    let Some(name) = name.filter(|name| content_type(name).is_some()) else {
+        return;
+    };
+
+    for key in [image_key(&name), thumbnail_key(&name)] {
+        if let Err(e) = storage.delete(&key).await {
+            tracing::warn!("Could not delete {}: {:?}", key, e);
+        }
+    }
+}
+
+/// The content type of an image from its name, `None` if it isn't a name
+/// we gave out, such as the URLs images used to be.
+pub fn content_type(name: &str) -> Option<&'static str> {
+    let (stem, extension) = name.split_once('.')?;
+    if stem.len() != 32 || !stem.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    match extension {
+        "jpg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        _ => None,
+    }
+}
+
+fn image_key(name: &str) -> String {
+    format!("images/{}", name)
+}
+
+fn thumbnail_key(name: &str) -> String {
+    format!("thumbnails/{}", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn content_type_of_names_we_gave_out() {
+        assert_eq!(content_type(&format!("{}.jpg", NAME)), Some("image/jpeg"));
+        assert_eq!(content_type(&format!("{}.png", NAME)), Some("image/png"));
+    }
+
+    #[test]
+    fn content_type_refuses_other_names() {
+        for name in [
+            "../../etc/passwd",
+            "../0123456789abcdef0123456789abcd.jpg",
+            "0123456789abcdef0123456789abcdef/../x.jpg",
+            "images/0123456789abcdef0123456789abcdef.jpg",
+            "0123456789abcdef0123456789abcdef.gif",
+            "0123456789abcdef0123456789abcdef.jpg.png",
+            "0123456789abcdef0123456789abcdeg.jpg",
+            "0123456789abcdef.jpg",
+            "https://example.com/a.jpg",
+            "",
+        ] {
+            assert_eq!(content_type(name), None, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{
+    authorization::Actor,
+    etag::IfMatch,
+    models::{
+        audit_model::{AuditAction, AuditEntityType},
+        item_model::{CreateItemDTO, ItemEntity, UpdateItemDTO},
+    },
+    services::{audit_service, image_service},
+    storage::Storage,
+    validation::ResultExt,
+    Result,
+};
+
+pub async fn get_all_items(db: &sqlx::Pool<sqlx::Postgres>) -> Result<Vec<ItemEntity>> {
+    let items = sqlx::query_as!(ItemEntity, "SELECT * FROM items ORDER BY id")
+        .fetch_all(db)
+        .await?;
+
+    Ok(items)
+}
+
+pub async fn get_item(db: &sqlx::Pool<sqlx::Postgres>, id: i32) -> Result<Option<ItemEntity>> {
+    let item = sqlx::query_as!(ItemEntity, "SELECT * FROM items WHERE id = $1", id)
+        .fetch_optional(db)
+        .await?;
+
+    Ok(item)
+}
+
+pub async fn create_item(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    data: CreateItemDTO,
+    actor: &Actor,
+) -> Result<ItemEntity> {
+    let mut tx = db.begin().await?;
+
+    let item = sqlx::query_as!(
+        ItemEntity,
+        "INSERT INTO items (sku, name, description, unit)
+        VALUES ($1, $2, $3, COALESCE($4, 'un')) RETURNING *",
+        data.sku,
+        data.name,
+        data.description,
+        data.unit
+    )
+    .fetch_one(&mut tx)
+    .await
+    .on_constraint("items_sku_key", "sku already taken")?;
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Create,
+        AuditEntityType::Item,
+        item.id,
+        None,
+        Some(&item),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(item)
+}
+
+pub async fn update_item(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+    data: UpdateItemDTO,
+    if_match: &IfMatch,
+    actor: &Actor,
+) -> Result<Option<ItemEntity>> {
+    let mut tx = db.begin().await?;
+
+    let Some(before) = sqlx::query_as!(
+        ItemEntity,
+        "SELECT * FROM items WHERE id = $1 FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+    if_match.check(Some(&before))?;
+
+    let item = sqlx::query_as!(
+        ItemEntity,
+        "UPDATE items SET
+            sku = COALESCE($1, sku),
+            name = COALESCE($2, name),
+            description = COALESCE($3, description),
+            unit = COALESCE($4, unit)
+        WHERE id = $5 RETURNING *",
+        data.sku,
+        data.name,
+        data.description,
+        data.unit,
+        id
+    )
+    .fetch_one(&mut tx)
+    .await
+    .on_constraint("items_sku_key", "sku already taken")?;
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::Item,
+        item.id,
+        Some(&before),
+        Some(&item),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(item))
+}
+
+/// Replaces the image of an item, `None` if there is no such item.
+pub async fn set_image(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    storage: &dyn Storage,
+    id: i32,
+    image: Option<String>,
+    if_match: &IfMatch,
+    actor: &Actor,
+) -> Result<Option<ItemEntity>> {
+    let mut tx = db.begin().await?;
+
+    let Some(before) = sqlx::query_as!(
+        ItemEntity,
+        "SELECT * FROM items WHERE id = $1 FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+    if_match.check(Some(&before))?;
+
+    let item = sqlx::query_as!(
+        ItemEntity,
+        "UPDATE items SET image = $1 WHERE id = $2 RETURNING *",
+        image,
+        id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::Item,
+        item.id,
+        Some(&before),
+        Some(&item),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    if before.image != item.image {
+        image_service::release_image(storage, before.image).await;
+    }
+
+    Ok(Some(item))
+}
+
+pub async fn delete_item(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    storage: &dyn Storage,
+    id: i32,
+    if_match: &IfMatch,
+    actor: &Actor,
+) -> Result<()> {
+    let mut tx = db.begin().await?;
+
+    let item = sqlx::query_as!(
+        ItemEntity,
+        "SELECT * FROM items WHERE id = $1 FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+    if_match.check(item.as_ref())?;
+    let Some(item) = item else {
+        return Ok(());
+    };
+
+    sqlx::query!("DELETE FROM items WHERE id = $1", id)
+        .execute(&mut tx)
+        .await
+        .on_constraint("stock_movements_item_id_fkey", "item has stock movements")?;
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Delete,
+        AuditEntityType::Item,
+        item.id,
+        Some(&item),
+        None,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    image_service::release_image(storage, item.image).await;
+
+    Ok(())
+}
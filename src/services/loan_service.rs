@@ -0,0 +1,205 @@
+use crate::{
+    authorization::Actor,
+    models::{
+        audit_model::{AuditAction, AuditEntityType},
+        loan_model::{CheckoutDTO, LoanEntity, LoanReturnEntity, ReturnLoanDTO},
+        stock_model::{MovementKind, NewMovement},
+    },
+    services::{audit_service, stock_service},
+    validation::{CustomError, ResultExt},
+    Result,
+};
+
+pub async fn get_loan(db: &sqlx::Pool<sqlx::Postgres>, id: i32) -> Result<Option<LoanEntity>> {
+    let loan = sqlx::query_as!(LoanEntity, "SELECT * FROM loans WHERE id = $1", id)
+        .fetch_optional(db)
+        .await?;
+
+    Ok(loan)
+}
+
+pub async fn get_open_loans_by_user(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+) -> Result<Vec<LoanEntity>> {
+    let loans = sqlx::query_as!(
+        LoanEntity,
+        "SELECT * FROM loans WHERE user_id = $1 AND returned_at IS NULL ORDER BY due_at",
+        user_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(loans)
+}
+
+pub async fn get_overdue_loans(db: &sqlx::Pool<sqlx::Postgres>) -> Result<Vec<LoanEntity>> {
+    let loans = sqlx::query_as!(
+        LoanEntity,
+        "SELECT * FROM loans WHERE returned_at IS NULL AND due_at < NOW() ORDER BY due_at"
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(loans)
+}
+
+pub async fn get_loans_by_item(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    item_id: i32,
+) -> Result<Vec<LoanEntity>> {
+    let loans = sqlx::query_as!(
+        LoanEntity,
+        "SELECT * FROM loans WHERE item_id = $1 ORDER BY created_at DESC",
+        item_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(loans)
+}
+
+pub async fn get_loan_returns(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    loan_id: i32,
+) -> Result<Vec<LoanReturnEntity>> {
+    let returns = sqlx::query_as!(
+        LoanReturnEntity,
+        "SELECT * FROM loan_returns WHERE loan_id = $1 ORDER BY id",
+        loan_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(returns)
+}
+
+/// Lends items from a place to `user_id`, taking them out of its stock.
+pub async fn checkout(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    data: CheckoutDTO,
+    user_id: i32,
+    actor: &Actor,
+) -> Result<LoanEntity> {
+    let mut tx = db.begin().await?;
+
+    let loan = sqlx::query_as!(
+        LoanEntity,
+        "INSERT INTO loans (item_id, place_id, user_id, quantity, due_at, note)
+        VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+        data.item_id,
+        data.place_id,
+        user_id,
+        data.quantity,
+        data.due_at,
+        data.note
+    )
+    .fetch_one(&mut tx)
+    .await
+    .on_constraint("loans_item_id_fkey", "item does not exist")
+    .on_constraint("loans_place_id_fkey", "place does not exist")?;
+
+    let movement = NewMovement {
+        item_id: loan.item_id,
+        place_id: loan.place_id,
+        kind: MovementKind::Issue,
+        quantity: -loan.quantity,
+        note: Some(format!("checkout of loan {}", loan.id)),
+        user_id,
+        transfer_id: None,
+        loan_id: Some(loan.id),
+        requisition_id: None,
+    };
+    stock_service::apply_movement(&mut tx, movement).await?;
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Create,
+        AuditEntityType::Loan,
+        loan.id,
+        None,
+        Some(&loan),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(loan)
+}
+
+/// Brings back some or all of what is still out on a loan, putting it back in
+/// the place it was taken from. The loan is closed once everything is back.
+pub async fn return_loan(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+    data: ReturnLoanDTO,
+    user_id: i32,
+    actor: &Actor,
+) -> Result<LoanEntity> {
+    let mut tx = db.begin().await?;
+
+    let before = sqlx::query_as!(
+        LoanEntity,
+        "SELECT * FROM loans WHERE id = $1 FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or(CustomError::NotFound)?;
+
+    let loan = sqlx::query_as!(
+        LoanEntity,
+        "UPDATE loans SET
+            returned_quantity = returned_quantity + $2,
+            returned_at = CASE WHEN returned_quantity + $2 = quantity THEN NOW() END
+        WHERE id = $1 RETURNING *",
+        id,
+        data.quantity
+    )
+    .fetch_optional(&mut tx)
+    .await
+    .on_constraint(
+        "loans_returned_quantity_check",
+        "quantity exceeds what is still on loan",
+    )?
+    .ok_or(CustomError::NotFound)?;
+
+    sqlx::query!(
+        "INSERT INTO loan_returns (loan_id, quantity, condition, user_id) VALUES ($1, $2, $3, $4)",
+        loan.id,
+        data.quantity,
+        data.condition,
+        user_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    let movement = NewMovement {
+        item_id: loan.item_id,
+        place_id: loan.place_id,
+        kind: MovementKind::Receipt,
+        quantity: data.quantity,
+        note: Some(format!("return of loan {}", loan.id)),
+        user_id,
+        transfer_id: None,
+        loan_id: Some(loan.id),
+        requisition_id: None,
+    };
+    stock_service::apply_movement(&mut tx, movement).await?;
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::Loan,
+        loan.id,
+        Some(&before),
+        Some(&loan),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(loan)
+}
@@ -0,0 +1,250 @@
+use chrono::{Duration, Utc};
+
+use sqlx::{Postgres, Transaction};
+
+use crate::{
+    authorization::Actor,
+    config::Config,
+    models::{
+        audit_model::{AuditAction, AuditEntityType},
+        lockout_model::{LockoutEntity, ThrottleScope},
+    },
+    services::audit_service,
+    validation::CustomError,
+    Result,
+};
+
+/// Refuses to go on while the email address or the client ip are locked out.
+/// Runs before the password is even looked at, so a locked out attacker gets
+/// no answers and costs us no hashing.
+pub async fn check(db: &sqlx::Pool<sqlx::Postgres>, email: &str, ip: Option<&str>) -> Result<()> {
+    let locked_until = sqlx::query_scalar!(
+        r#"SELECT MAX(locked_until) FROM login_throttles
+        WHERE ((scope = 'email' AND key = $1) OR (scope = 'ip' AND key = $2))
+            AND locked_until > NOW()"#,
+        email.to_lowercase(),
+        ip
+    )
+    .fetch_one(db)
+    .await?;
+
+    match locked_until {
+        Some(locked_until) => Err(CustomError::TooManyRequests {
+            retry_after: (locked_until - Utc::now()).num_seconds().max(1),
+        }),
+        None => Ok(()),
+    }
+}
+
+pub async fn record_failure(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    config: &Config,
+    email: &str,
+    ip: Option<&str>,
+) -> Result<()> {
+    let email = email.to_lowercase();
+    let keys = [
+        Some((ThrottleScope::Email, email.as_str())),
+        ip.map(|ip| (ThrottleScope::Ip, ip)),
+    ];
+
+    for (scope, key) in keys.into_iter().flatten() {
+        record_key_failure(db, config, scope, key).await?;
+    }
+
+    Ok(())
+}
+
+/// Forgets the failures of an email address once its login is complete, the
+/// second factor included. Those of the ip are kept, or an attacker could
+/// wipe them by logging into an account of their own between guesses.
+pub async fn record_success(db: &sqlx::Pool<sqlx::Postgres>, email: &str) -> Result<()> {
+    sqlx::query!(
+        "DELETE FROM login_throttles WHERE scope = 'email' AND key = $1",
+        email.to_lowercase()
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_active_lockouts(db: &sqlx::Pool<sqlx::Postgres>) -> Result<Vec<LockoutEntity>> {
+    let lockouts = sqlx::query_as!(
+        LockoutEntity,
+        r#"SELECT id, scope as "scope: ThrottleScope", key, user_id, failures, locked_until, unlocked_at, unlocked_by, created_at
+        FROM lockouts
+        WHERE unlocked_at IS NULL AND locked_until > NOW()
+        ORDER BY created_at DESC"#
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(lockouts)
+}
+
+/// Lifts a lockout before it runs out, forgetting the failures behind it.
+pub async fn unlock(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+    actor: &Actor,
+) -> Result<Option<LockoutEntity>> {
+    let mut tx = db.begin().await?;
+
+    let before = sqlx::query_as!(
+        LockoutEntity,
+        r#"SELECT id, scope as "scope: ThrottleScope", key, user_id, failures, locked_until, unlocked_at, unlocked_by, created_at
+        FROM lockouts WHERE id = $1 FOR UPDATE"#,
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+    let Some(before) = before else {
+        return Ok(None);
+    };
+
+    let lockout = lift(&mut tx, before, actor).await?;
+    sqlx::query!(
+        "DELETE FROM login_throttles WHERE scope = $1 AND key = $2",
+        lockout.scope as ThrottleScope,
+        lockout.key
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(lockout))
+}
+
+/// Lifts every lockout on the email address of `user_id`.
+pub async fn unlock_user(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+    actor: &Actor,
+) -> Result<()> {
+    let mut tx = db.begin().await?;
+
+    let lockouts = sqlx::query_as!(
+        LockoutEntity,
+        r#"SELECT id, scope as "scope: ThrottleScope", key, user_id, failures, locked_until, unlocked_at, unlocked_by, created_at
+        FROM lockouts WHERE user_id = $1 AND unlocked_at IS NULL FOR UPDATE"#,
+        user_id
+    )
+    .fetch_all(&mut tx)
+    .await?;
+    for before in lockouts {
+        lift(&mut tx, before, actor).await?;
+    }
+
+    sqlx::query!(
+        "DELETE FROM login_throttles
+        WHERE scope = 'email' AND key = (SELECT LOWER(email) FROM users WHERE id = $1)",
+        user_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Marks a lockout as lifted by `actor`, keeping who lifted one that already was.
+async fn lift(
+    tx: &mut Transaction<'_, Postgres>,
+    before: LockoutEntity,
+    actor: &Actor,
+) -> Result<LockoutEntity> {
+    let lockout = sqlx::query_as!(
+        LockoutEntity,
+        r#"UPDATE lockouts SET unlocked_at = COALESCE(unlocked_at, NOW()), unlocked_by = COALESCE(unlocked_by, $2)
+        WHERE id = $1
+        RETURNING id, scope as "scope: ThrottleScope", key, user_id, failures, locked_until, unlocked_at, unlocked_by, created_at"#,
+        before.id,
+        actor.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit_service::record(
+        tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::Lockout,
+        lockout.id,
+        Some(&before),
+        Some(&lockout),
+    )
+    .await?;
+
+    Ok(lockout)
+}
+
+async fn record_key_failure(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    config: &Config,
+    scope: ThrottleScope,
+    key: &str,
+) -> Result<()> {
+    let window = Duration::seconds(config.login_lockout_max_seconds);
+
+    let failures = sqlx::query_scalar!(
+        "INSERT INTO login_throttles (scope, key, failures) VALUES ($1, $2, 1)
+        ON CONFLICT (scope, key) DO UPDATE SET
+            failures = CASE WHEN login_throttles.last_failure_at < $3 THEN 1
+                ELSE login_throttles.failures + 1 END,
+            last_failure_at = NOW()
+        RETURNING failures",
+        scope as ThrottleScope,
+        key,
+        Utc::now() - window
+    )
+    .fetch_one(db)
+    .await?;
+
+    let max_failures = match scope {
+        ThrottleScope::Email => config.login_max_failures,
+        ThrottleScope::Ip => config.login_max_failures_per_ip,
+    };
+    if failures < max_failures {
+        return Ok(());
+    }
+
+    // every failure past the limit doubles the lockout, up to the max
+    let doublings = (failures - max_failures).min(30) as u32;
+    let seconds = config
+        .login_lockout_seconds
+        .saturating_mul(2i64.pow(doublings))
+        .min(config.login_lockout_max_seconds);
+    let locked_until = Utc::now() + Duration::seconds(seconds);
+
+    sqlx::query!(
+        "UPDATE login_throttles SET locked_until = $3 WHERE scope = $1 AND key = $2",
+        scope as ThrottleScope,
+        key,
+        locked_until
+    )
+    .execute(db)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO lockouts (scope, key, user_id, failures, locked_until)
+        VALUES ($1, $2::VARCHAR, (SELECT id FROM users WHERE LOWER(email) = $2::VARCHAR AND deleted_at IS NULL AND $1 = 'email'::throttle_scope), $3, $4)",
+        scope as ThrottleScope,
+        key,
+        failures,
+        locked_until
+    )
+    .execute(db)
+    .await?;
+
+    tracing::warn!(
+        "Locked out {:?} {} after {} failed logins",
+        scope,
+        key,
+        failures
+    );
+
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    authorization::Actor,
+    models::{
+        audit_model::{AuditAction, AuditEntityType},
+        profile_model::ProfileEntity,
+    },
+    oidc::{IdClaims, OidcProvider},
+    services::{audit_service, profile_service, session_service, user_service},
+    validation::{CustomError, ResultExt},
+    Result,
+};
+
+const LOGIN_TTL_MINUTES: i64 = 10;
+
+/// The cookie holding the `state` of the login the browser started, so a
+/// callback URL handed over by someone else can't log it in as them.
+pub const STATE_COOKIE: &str = "oidc_state";
+
+/// The `Set-Cookie` value binding `state` to the browser, or dropping the
+/// binding when `state` is `None`.
+pub fn state_cookie(state: Option<&str>, secure: bool) -> String {
+    let (value, max_age) = match state {
+        Some(state) => (state, LOGIN_TTL_MINUTES * 60),
+        None => ("", 0),
+    };
+
+    format!(
+        "{}={}; Path=/users/oidc; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        STATE_COOKIE,
+        value,
+        max_age,
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+/// Remembers a new login and returns the provider URL to send the user to,
+/// along with the `state` to bind to their browser.
+pub async fn start_login(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    provider: &OidcProvider,
+) -> Result<(String, String)> {
+    let state = session_service::generate_token();
+    let nonce = session_service::generate_token();
+    let code_verifier = session_service::generate_token();
+    let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+    sqlx::query!("DELETE FROM oidc_logins WHERE expires_at < NOW()")
+        .execute(db)
+        .await?;
+
+    sqlx::query!(
+        "INSERT INTO oidc_logins (state_hash, nonce, code_verifier, expires_at)
+        VALUES ($1, $2, $3, $4)",
+        session_service::hash_token(&state),
+        nonce,
+        code_verifier,
+        Utc::now() + Duration::minutes(LOGIN_TTL_MINUTES)
+    )
+    .execute(db)
+    .await?;
+
+    let url = provider
+        .authorization_url(&state, &nonce, &code_challenge)
+        .await?;
+
+    Ok((url, state))
+}
+
+/// Finishes the login started with `state`, returning the user the provider
+/// vouched for. `browser_state` is what the browser was given when it
+/// started the login, and has to be the same.
+pub async fn finish_login(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    provider: &OidcProvider,
+    code: &str,
+    state: &str,
+    browser_state: Option<&str>,
+    actor: &Actor,
+) -> Result<ProfileEntity> {
+    if browser_state != Some(state) {
+        return Err(CustomError::Unauthorized);
+    }
+
+    let login = sqlx::query!(
+        "DELETE FROM oidc_logins WHERE state_hash = $1 AND expires_at > NOW()
+        RETURNING nonce, code_verifier",
+        session_service::hash_token(state)
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(CustomError::Unauthorized)?;
+
+    let claims = provider
+        .exchange(code, &login.code_verifier, &login.nonce)
+        .await?
+        .ok_or(CustomError::Unauthorized)?;
+
+    let user_id = link_or_provision(db, claims, actor).await?;
+
+    // identities stay linked to deleted accounts, which may not log in
+    profile_service::get_user(user_id, db)
+        .await?
+        .filter(|user| user.deleted_at.is_none())
+        .ok_or(CustomError::Unauthorized)
+}
+
+/// Finds the user behind an identity, linking it to the account with the
+/// same email on first login or creating one if there is none. Emails are
+/// only trusted once the provider verified them.
+async fn link_or_provision(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    claims: IdClaims,
+    actor: &Actor,
+) -> Result<i32> {
+    let mut tx = db.begin().await?;
+
+    let linked = sqlx::query_scalar!(
+        "UPDATE identities SET last_login_at = NOW(), email = COALESCE($3, email)
+        WHERE issuer = $1 AND subject = $2
+        RETURNING user_id",
+        claims.iss,
+        claims.sub,
+        claims.email
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+
+    if let Some(user_id) = linked {
+        tx.commit().await?;
+        return Ok(user_id);
+    }
+
+    let email = match claims.email {
+        Some(email) if claims.email_verified => email,
+        _ => {
+            tracing::warn!("Provider did not vouch for the email of {}", claims.sub);
+            return Err(CustomError::Unauthorized);
+        }
+    };
+
+    let existing = sqlx::query_scalar!(
+        "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW())
+        WHERE LOWER(email) = LOWER($1) AND deleted_at IS NULL
+        RETURNING id",
+        email
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+
+    let user_id = match existing {
+        Some(id) => id,
+        None => {
+            let name = claims
+                .name
+                .or(claims.preferred_username)
+                .unwrap_or_else(|| email.clone());
+
+            // same bootstrap rule as signing up with a password
+            user_service::lock_bootstrap(&mut tx).await?;
+            let user_id = sqlx::query_scalar!(
+                r#"INSERT INTO users (name, email, role, email_verified_at)
+                VALUES ($1, $2, CASE WHEN EXISTS (SELECT 1 FROM users) THEN 'requester' ELSE 'admin' END::user_role, NOW())
+                RETURNING id"#,
+                name,
+                email
+            )
+            .fetch_one(&mut tx)
+            .await
+            .on_constraint("users_email_key", "email already taken")?;
+
+            let user = user_service::lock_profile(&mut tx, user_id).await?;
+            audit_service::record(
+                &mut tx,
+                actor,
+                AuditAction::Create,
+                AuditEntityType::User,
+                user_id,
+                None,
+                user.as_ref(),
+            )
+            .await?;
+
+            user_id
+        }
+    };
+
+    sqlx::query!(
+        "INSERT INTO identities (user_id, issuer, subject, email) VALUES ($1, $2, $3, $4)",
+        user_id,
+        claims.iss,
+        claims.sub,
+        email
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(user_id)
+}
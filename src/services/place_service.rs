@@ -1,15 +1,76 @@
+use std::collections::HashMap;
+
+use crate::{
+    authorization::Actor,
+    etag::IfMatch,
+    models::audit_model::{AuditAction, AuditEntityType},
+    services::{audit_service, image_service},
+    storage::Storage,
+};
 use crate::{models::place_model::UpdatePlaceDTO, Result};
+use sqlx::{Postgres, QueryBuilder, Transaction};
+
 use crate::{
-    models::place_model::{CreatePlaceDTO, PlaceEntity},
-    validation::ResultExt,
+    models::place_model::{
+        Coordinates, CreatePlaceDTO, NearbyPlace, NearbyQuery, PlaceEntity, PlaceNode, PlaceQuery,
+    },
+    pagination::{self, ListFilter, ListQuery, Page, SortKey},
+    validation::{field_error, ResultExt},
 };
 
-pub async fn get_all_places(db: &sqlx::Pool<sqlx::Postgres>) -> Result<Vec<PlaceEntity>> {
-    let places = sqlx::query_as!(PlaceEntity, "SELECT * FROM places")
-        .fetch_all(db)
-        .await?;
+/// Serializes the checks that keep places from ending up inside themselves.
+const PLACE_TREE_LOCK: i64 = 0x706c_6163_6573;
 
-    Ok(places)
+/// In meters, when looking for places nearby.
+const DEFAULT_RADIUS: f64 = 1000.0;
+
+impl ListFilter for PlaceQuery {
+    const SORT_KEYS: &'static [SortKey] = &[
+        SortKey {
+            name: "id",
+            column: "id",
+            sql_type: "INTEGER",
+        },
+        SortKey {
+            name: "name",
+            column: "name",
+            sql_type: "VARCHAR",
+        },
+        SortKey {
+            name: "created_at",
+            column: "created_at",
+            sql_type: "TIMESTAMPTZ",
+        },
+        SortKey {
+            name: "updated_at",
+            column: "updated_at",
+            sql_type: "TIMESTAMPTZ",
+        },
+    ];
+
+    fn push_conditions(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        if !self.include_deleted {
+            query.push(" AND deleted_at IS NULL");
+        }
+        if let Some(name) = &self.name {
+            query
+                .push(" AND name ILIKE ")
+                .push_bind(pagination::contains_pattern(name));
+        }
+        if let Some(created_after) = self.created_after {
+            query.push(" AND created_at > ").push_bind(created_after);
+        }
+        if let Some(created_before) = self.created_before {
+            query.push(" AND created_at < ").push_bind(created_before);
+        }
+    }
+}
+
+pub async fn get_all_places(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    query: ListQuery<PlaceQuery>,
+) -> Result<Page<PlaceEntity>> {
+    query.fetch(db, "*", "places").await
 }
 
 pub async fn get_place(db: &sqlx::Pool<sqlx::Postgres>, id: i32) -> Result<Option<PlaceEntity>> {
@@ -20,47 +81,444 @@ pub async fn get_place(db: &sqlx::Pool<sqlx::Postgres>, id: i32) -> Result<Optio
     Ok(place)
 }
 
+/// Places within `radius` meters of a point, closest first.
+pub async fn get_nearby(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    query: NearbyQuery,
+) -> Result<Vec<NearbyPlace>> {
+    let center = Coordinates {
+        lat: query.lat,
+        lng: query.lng,
+    };
+    let radius = query.radius.unwrap_or(DEFAULT_RADIUS);
+
+    // the box around the circle narrows down the rows, the distance is
+    // worked out on what's left
+    let (d_lat, d_lng) = center.bounds(radius);
+    let d_lng =
+        d_lng.filter(|d_lng| (center.lng - d_lng) >= -180.0 && (center.lng + d_lng) <= 180.0);
+    let places = sqlx::query_as!(
+        PlaceEntity,
+        "SELECT * FROM places
+        WHERE deleted_at IS NULL AND lat BETWEEN $1 AND $2
+        AND ($3::FLOAT8 IS NULL OR lng BETWEEN $3 AND $4)",
+        center.lat - d_lat,
+        center.lat + d_lat,
+        d_lng.map(|d_lng| center.lng - d_lng),
+        d_lng.map(|d_lng| center.lng + d_lng)
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut nearby: Vec<NearbyPlace> = places
+        .into_iter()
+        .filter_map(|place| {
+            let distance = place.coordinates()?.distance_to(&center);
+            (distance <= radius).then_some(NearbyPlace { place, distance })
+        })
+        .collect();
+    nearby.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+
+    Ok(nearby)
+}
+
+/// Every place that is on the map.
+pub async fn get_located_places(db: &sqlx::Pool<sqlx::Postgres>) -> Result<Vec<PlaceEntity>> {
+    let places = sqlx::query_as!(
+        PlaceEntity,
+        "SELECT * FROM places WHERE deleted_at IS NULL AND lat IS NOT NULL ORDER BY id"
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(places)
+}
+
+/// The place with every place inside of it, deleted ones left out.
+pub async fn get_subtree(db: &sqlx::Pool<sqlx::Postgres>, id: i32) -> Result<Option<PlaceNode>> {
+    let places = sqlx::query_as!(
+        PlaceEntity,
+        "SELECT * FROM places WHERE id = $1 OR id IN (
+            WITH RECURSIVE subtree AS (
+                SELECT id FROM places WHERE id = $1
+                UNION
+                SELECT p.id FROM places p JOIN subtree s ON p.parent_id = s.id
+                WHERE p.deleted_at IS NULL
+            )
+            SELECT id FROM subtree
+        )
+        ORDER BY name",
+        id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut children: HashMap<i32, Vec<PlaceEntity>> = HashMap::new();
+    let mut root = None;
+    for place in places {
+        match place.parent_id {
+            _ if place.id == id => root = Some(place),
+            Some(parent_id) => children.entry(parent_id).or_default().push(place),
+            None => {}
+        }
+    }
+
+    Ok(root.map(|root| build_node(root, &mut children)))
+}
+
+fn build_node(place: PlaceEntity, children: &mut HashMap<i32, Vec<PlaceEntity>>) -> PlaceNode {
+    let nodes = children
+        .remove(&place.id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|child| build_node(child, children))
+        .collect();
+
+    PlaceNode {
+        place,
+        children: nodes,
+    }
+}
+
+/// The places leading to a place, from the top one down to the place itself.
+/// Empty when there is no such place.
+pub async fn get_path(db: &sqlx::Pool<sqlx::Postgres>, id: i32) -> Result<Vec<PlaceEntity>> {
+    let places = sqlx::query_as!(
+        PlaceEntity,
+        "SELECT * FROM places WHERE id IN (
+            WITH RECURSIVE ancestors AS (
+                SELECT id, parent_id FROM places WHERE id = $1
+                UNION
+                SELECT p.id, p.parent_id FROM places p JOIN ancestors a ON p.id = a.parent_id
+            )
+            SELECT id FROM ancestors
+        )",
+        id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut by_id: HashMap<i32, PlaceEntity> =
+        places.into_iter().map(|place| (place.id, place)).collect();
+    let mut path = vec![];
+    let mut next = Some(id);
+    while let Some(place) = next.and_then(|id| by_id.remove(&id)) {
+        next = place.parent_id;
+        path.push(place);
+    }
+    path.reverse();
+
+    Ok(path)
+}
+
+/// Checks that `place_id` may be put inside of `parent_id`: the parent must
+/// be around and not inside of the place already.
+async fn ensure_parent(
+    tx: &mut Transaction<'_, Postgres>,
+    place_id: Option<i32>,
+    parent_id: i32,
+) -> Result<()> {
+    // two moves checked at the same time could close a loop between them
+    sqlx::query!("SELECT pg_advisory_xact_lock($1)", PLACE_TREE_LOCK)
+        .execute(&mut *tx)
+        .await?;
+
+    let parent = sqlx::query_scalar!(
+        r#"SELECT deleted_at IS NOT NULL as "deleted!" FROM places WHERE id = $1"#,
+        parent_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    match parent {
+        None => return Err(field_error("parent_id", "place does not exist")),
+        Some(true) => return Err(field_error("parent_id", "place was deleted")),
+        Some(false) => {}
+    }
+
+    let Some(place_id) = place_id else {
+        return Ok(());
+    };
+    let inside = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            WITH RECURSIVE ancestors AS (
+                SELECT id, parent_id FROM places WHERE id = $1
+                UNION
+                SELECT p.id, p.parent_id FROM places p JOIN ancestors a ON p.id = a.parent_id
+            )
+            SELECT 1 FROM ancestors WHERE id = $2
+        ) as "exists!""#,
+        parent_id,
+        place_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if inside {
+        return Err(field_error(
+            "parent_id",
+            "place can not be inside of itself",
+        ));
+    }
+
+    Ok(())
+}
+
 pub async fn create_place(
     db: &sqlx::Pool<sqlx::Postgres>,
     data: CreatePlaceDTO,
+    actor: &Actor,
 ) -> Result<PlaceEntity> {
+    let mut tx = db.begin().await?;
+
+    if let Some(parent_id) = data.parent_id {
+        ensure_parent(&mut tx, None, parent_id).await?;
+    }
+
     let place = sqlx::query_as!(
         PlaceEntity,
-        "INSERT INTO places (name, description, image) VALUES ($1, $2, $3) RETURNING *",
+        "INSERT INTO places (name, description, parent_id, lat, lng)
+        VALUES ($1, $2, $3, $4, $5) RETURNING *",
         data.name,
         data.description,
-        data.image
+        data.parent_id,
+        data.coordinates.map(|c| c.lat),
+        data.coordinates.map(|c| c.lng)
     )
-    .fetch_one(db)
+    .fetch_one(&mut tx)
     .await
     .on_constraint("places_name_key", "name already taken")?;
 
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Create,
+        AuditEntityType::Place,
+        place.id,
+        None,
+        Some(&place),
+    )
+    .await?;
+
+    tx.commit().await?;
+
     Ok(place)
 }
 
+/// Applies a partial update, `None` if there is no such place.
 pub async fn update_place(
     db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
     data: UpdatePlaceDTO,
-) -> Result<PlaceEntity> {
+    if_match: &IfMatch,
+    actor: &Actor,
+) -> Result<Option<PlaceEntity>> {
+    let mut tx = db.begin().await?;
+
+    let Some(before) = sqlx::query_as!(
+        PlaceEntity,
+        "SELECT * FROM places WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+    if_match.check(Some(&before))?;
+
+    let parent_id = data.parent_id.unwrap_or(before.parent_id);
+    if let Some(parent_id) = parent_id.filter(|&id| before.parent_id != Some(id)) {
+        ensure_parent(&mut tx, Some(id), parent_id).await?;
+    }
+    let coordinates = data.coordinates.unwrap_or_else(|| before.coordinates());
+
     let place = sqlx::query_as!(
         PlaceEntity,
-        "UPDATE places SET name = $1, description = $2, image = $3 WHERE id = $4 RETURNING *",
-        data.name,
-        data.description,
-        data.image,
-        data.id
+        "UPDATE places SET name = $1, description = $2, parent_id = $3, lat = $4, lng = $5
+        WHERE id = $6 RETURNING *",
+        data.name.as_ref().unwrap_or(&before.name),
+        data.description
+            .unwrap_or_else(|| before.description.clone()),
+        parent_id,
+        coordinates.map(|c| c.lat),
+        coordinates.map(|c| c.lng),
+        id
     )
-    .fetch_one(db)
+    .fetch_one(&mut tx)
     .await
     .on_constraint("places_name_key", "name already taken")?;
 
-    Ok(place)
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::Place,
+        place.id,
+        Some(&before),
+        Some(&place),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(place))
+}
+
+/// Replaces the image of a place, `None` if there is no such place.
+pub async fn set_image(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    storage: &dyn Storage,
+    id: i32,
+    image: Option<String>,
+    if_match: &IfMatch,
+    actor: &Actor,
+) -> Result<Option<PlaceEntity>> {
+    let mut tx = db.begin().await?;
+
+    let Some(before) = sqlx::query_as!(
+        PlaceEntity,
+        "SELECT * FROM places WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+    if_match.check(Some(&before))?;
+
+    let place = sqlx::query_as!(
+        PlaceEntity,
+        "UPDATE places SET image = $1 WHERE id = $2 RETURNING *",
+        image,
+        id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::Place,
+        place.id,
+        Some(&before),
+        Some(&place),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    if before.image != place.image {
+        image_service::release_image(storage, before.image).await;
+    }
+
+    Ok(Some(place))
 }
 
-pub async fn delete_place(db: &sqlx::Pool<sqlx::Postgres>, id: i32) -> Result<()> {
-    sqlx::query!("DELETE FROM places WHERE id = $1", id)
-        .execute(db)
+/// Deletes an empty place, keeping its row for the movements that point at it.
+pub async fn delete_place(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+    if_match: &IfMatch,
+    actor: &Actor,
+) -> Result<()> {
+    let mut tx = db.begin().await?;
+
+    let place = sqlx::query_as!(
+        PlaceEntity,
+        "SELECT * FROM places WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+    if_match.check(place.as_ref())?;
+    let Some(place) = place else {
+        return Ok(());
+    };
+
+    let holds_stock = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM stock_levels WHERE place_id = $1 AND quantity > 0) as "exists!""#,
+        id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+    if holds_stock {
+        return Err(field_error("id", "place still holds stock"));
+    }
+
+    let holds_places = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM places WHERE parent_id = $1 AND deleted_at IS NULL) as "exists!""#,
+        id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+    if holds_places {
+        return Err(field_error("id", "place still holds other places"));
+    }
+
+    sqlx::query!("UPDATE places SET deleted_at = NOW() WHERE id = $1", id)
+        .execute(&mut tx)
         .await?;
 
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Delete,
+        AuditEntityType::Place,
+        place.id,
+        Some(&place),
+        None,
+    )
+    .await?;
+
+    tx.commit().await?;
+
     Ok(())
 }
+
+/// Brings back a deleted place, `None` if there is no such deleted place.
+pub async fn restore_place(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+    actor: &Actor,
+) -> Result<Option<PlaceEntity>> {
+    let mut tx = db.begin().await?;
+
+    let place = sqlx::query_as!(
+        PlaceEntity,
+        "UPDATE places SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL RETURNING *",
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await
+    .on_constraint("places_name_key", "name already taken")?;
+
+    if let Some(parent_id) = place.as_ref().and_then(|place| place.parent_id) {
+        let parent_deleted = sqlx::query_scalar!(
+            r#"SELECT deleted_at IS NOT NULL as "deleted!" FROM places WHERE id = $1"#,
+            parent_id
+        )
+        .fetch_one(&mut tx)
+        .await?;
+        if parent_deleted {
+            return Err(field_error("parent_id", "place was deleted"));
+        }
+    }
+
+    if let Some(place) = &place {
+        audit_service::record(
+            &mut tx,
+            actor,
+            AuditAction::Restore,
+            AuditEntityType::Place,
+            place.id,
+            None,
+            Some(place),
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(place)
+}
@@ -1,12 +1,69 @@
-use crate::models::profile_model::ProfileEntity;
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::models::{
+    profile_model::{ProfileEntity, ProfileQuery},
+    user_model::Role,
+};
+use crate::pagination::{self, ListFilter, ListQuery, Page, SortKey};
 use crate::Result;
 
-pub async fn get_all_users(state: &sqlx::Pool<sqlx::Postgres>) -> Result<Vec<ProfileEntity>> {
-    let users = sqlx::query_as!(ProfileEntity, "SELECT id, name, email FROM users")
-        .fetch_all(state)
-        .await?;
+impl ListFilter for ProfileQuery {
+    const SORT_KEYS: &'static [SortKey] = &[
+        SortKey {
+            name: "id",
+            column: "id",
+            sql_type: "INTEGER",
+        },
+        SortKey {
+            name: "name",
+            column: "name",
+            sql_type: "VARCHAR",
+        },
+        SortKey {
+            name: "email",
+            column: "email",
+            sql_type: "VARCHAR",
+        },
+    ];
+
+    fn push_conditions(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        if !self.include_deleted {
+            query.push(" AND deleted_at IS NULL");
+        }
+        if let Some(name) = &self.name {
+            query
+                .push(" AND name ILIKE ")
+                .push_bind(pagination::contains_pattern(name));
+        }
+        if let Some(email) = &self.email {
+            query
+                .push(" AND email ILIKE ")
+                .push_bind(pagination::contains_pattern(email));
+        }
+        if let Some(role) = self.role {
+            query.push(" AND role = ").push_bind(role);
+        }
+        if let Some(created_after) = self.created_after {
+            query.push(" AND created_at > ").push_bind(created_after);
+        }
+        if let Some(created_before) = self.created_before {
+            query.push(" AND created_at < ").push_bind(created_before);
+        }
+    }
+}
 
-    Ok(users)
+pub async fn get_all_users(
+    query: ListQuery<ProfileQuery>,
+    state: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Page<ProfileEntity>> {
+    query
+        .fetch(
+            state,
+            "id, name, email, role, email_verified_at IS NOT NULL AS verified,
+            totp_enabled_at IS NOT NULL AS two_factor, deleted_at",
+            "users",
+        )
+        .await
 }
 
 pub async fn get_user(
@@ -15,7 +72,9 @@ pub async fn get_user(
 ) -> Result<Option<ProfileEntity>> {
     let user = sqlx::query_as!(
         ProfileEntity,
-        "SELECT id, name, email from users WHERE id = $1",
+        r#"SELECT id, name, email, role as "role: Role", email_verified_at IS NOT NULL as "verified!",
+        totp_enabled_at IS NOT NULL as "two_factor!", deleted_at
+        FROM users WHERE id = $1"#,
         id
     )
     .fetch_optional(state)
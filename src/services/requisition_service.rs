@@ -0,0 +1,389 @@
+use sqlx::{Postgres, Transaction};
+
+use crate::{
+    authorization::Actor,
+    models::{
+        audit_model::{AuditAction, AuditEntityType},
+        requisition_model::{
+            CreateRequisitionDTO, FulfilRequisitionDTO, RequisitionBody, RequisitionEntity,
+            RequisitionLineEntity, RequisitionLineStatus, RequisitionQuery, RequisitionStatus,
+        },
+        stock_model::{MovementKind, NewMovement},
+    },
+    services::{audit_service, stock_service},
+    validation::{CustomError, ResultExt},
+    Result,
+};
+
+pub async fn get_requisitions(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    query: RequisitionQuery,
+) -> Result<Vec<RequisitionEntity>> {
+    let requisitions = sqlx::query_as!(
+        RequisitionEntity,
+        r#"SELECT id, user_id, status as "status: RequisitionStatus", note, place_id, approver_id, created_at, updated_at
+        FROM requisitions
+        WHERE ($1::requisition_status IS NULL OR status = $1)
+        ORDER BY id"#,
+        query.status as Option<RequisitionStatus>
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(requisitions)
+}
+
+pub async fn get_requisitions_by_user(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+) -> Result<Vec<RequisitionEntity>> {
+    let requisitions = sqlx::query_as!(
+        RequisitionEntity,
+        r#"SELECT id, user_id, status as "status: RequisitionStatus", note, place_id, approver_id, created_at, updated_at
+        FROM requisitions WHERE user_id = $1 ORDER BY id DESC"#,
+        user_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(requisitions)
+}
+
+pub async fn get_requisition(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+) -> Result<Option<RequisitionBody>> {
+    let requisition = sqlx::query_as!(
+        RequisitionEntity,
+        r#"SELECT id, user_id, status as "status: RequisitionStatus", note, place_id, approver_id, created_at, updated_at
+        FROM requisitions WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    match requisition {
+        Some(requisition) => Ok(Some(RequisitionBody {
+            lines: get_lines(db, requisition.id).await?,
+            requisition,
+        })),
+        None => Ok(None),
+    }
+}
+
+pub async fn create_requisition(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    data: CreateRequisitionDTO,
+    user_id: i32,
+    actor: &Actor,
+) -> Result<RequisitionBody> {
+    let mut tx = db.begin().await?;
+
+    let requisition = sqlx::query_as!(
+        RequisitionEntity,
+        r#"INSERT INTO requisitions (user_id, note) VALUES ($1, $2)
+        RETURNING id, user_id, status as "status: RequisitionStatus", note, place_id, approver_id, created_at, updated_at"#,
+        user_id,
+        data.note
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    let mut lines = Vec::with_capacity(data.lines.len());
+    for line in data.lines {
+        let line = sqlx::query_as!(
+            RequisitionLineEntity,
+            r#"INSERT INTO requisition_lines (requisition_id, item_id, quantity) VALUES ($1, $2, $3)
+            RETURNING id, requisition_id, item_id, quantity, status as "status: RequisitionLineStatus""#,
+            requisition.id,
+            line.item_id,
+            line.quantity
+        )
+        .fetch_one(&mut tx)
+        .await
+        .on_constraint("requisition_lines_item_id_fkey", "item does not exist")
+        .on_constraint(
+            "requisition_lines_requisition_id_item_id_key",
+            "item requested more than once",
+        )?;
+        lines.push(line);
+    }
+
+    let requisition = RequisitionBody { requisition, lines };
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Create,
+        AuditEntityType::Requisition,
+        requisition.requisition.id,
+        None,
+        Some(&requisition),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(requisition)
+}
+
+pub async fn submit_requisition(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+    user_id: i32,
+    actor: &Actor,
+) -> Result<RequisitionEntity> {
+    let mut tx = db.begin().await?;
+
+    let before = lock_requisition(&mut tx, id).await?;
+    if before.user_id != user_id {
+        return Err(CustomError::Forbidden);
+    }
+    let requisition = set_status(&mut tx, before.clone(), RequisitionStatus::Submitted).await?;
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::Requisition,
+        id,
+        Some(&before),
+        Some(&requisition),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(requisition)
+}
+
+pub async fn cancel_requisition(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+    user_id: i32,
+    actor: &Actor,
+) -> Result<RequisitionEntity> {
+    let mut tx = db.begin().await?;
+
+    let before = lock_requisition(&mut tx, id).await?;
+    if before.user_id != user_id {
+        return Err(CustomError::Forbidden);
+    }
+    let requisition = set_status(&mut tx, before.clone(), RequisitionStatus::Cancelled).await?;
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::Requisition,
+        id,
+        Some(&before),
+        Some(&requisition),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(requisition)
+}
+
+/// Approves or rejects a single line of a submitted requisition. Once no line
+/// is pending the requisition is approved if any line was, rejected otherwise.
+pub async fn decide_line(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+    line_id: i32,
+    status: RequisitionLineStatus,
+    approver_id: i32,
+    actor: &Actor,
+) -> Result<RequisitionBody> {
+    let mut tx = db.begin().await?;
+
+    let requisition = lock_requisition(&mut tx, id).await?;
+    // whoever asked for the materials can't also be the one approving them
+    if requisition.user_id == approver_id {
+        return Err(CustomError::Forbidden);
+    }
+    if requisition.status != RequisitionStatus::Submitted {
+        return Err(CustomError::LineNotDecidable {
+            status: requisition.status.as_str(),
+            decision: status.as_str(),
+        });
+    }
+    let before = RequisitionBody {
+        requisition: requisition.clone(),
+        lines: get_lines(&mut tx, id).await?,
+    };
+
+    let updated = sqlx::query!(
+        "UPDATE requisition_lines SET status = $3 WHERE id = $1 AND requisition_id = $2",
+        line_id,
+        id,
+        status as RequisitionLineStatus
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        return Err(CustomError::NotFound);
+    }
+
+    let lines = get_lines(&mut tx, id).await?;
+    let requisition = if lines
+        .iter()
+        .any(|l| l.status == RequisitionLineStatus::Pending)
+    {
+        requisition
+    } else {
+        sqlx::query!(
+            "UPDATE requisitions SET approver_id = $2 WHERE id = $1",
+            id,
+            approver_id
+        )
+        .execute(&mut tx)
+        .await?;
+
+        let next = if lines
+            .iter()
+            .any(|l| l.status == RequisitionLineStatus::Approved)
+        {
+            RequisitionStatus::Approved
+        } else {
+            RequisitionStatus::Rejected
+        };
+        set_status(&mut tx, requisition, next).await?
+    };
+
+    let requisition = RequisitionBody { requisition, lines };
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::Requisition,
+        id,
+        Some(&before),
+        Some(&requisition),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(requisition)
+}
+
+/// Hands out the approved lines of a requisition, issuing them from the stock
+/// of the given place.
+pub async fn fulfil_requisition(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+    data: FulfilRequisitionDTO,
+    user_id: i32,
+    actor: &Actor,
+) -> Result<RequisitionBody> {
+    let mut tx = db.begin().await?;
+
+    let before = lock_requisition(&mut tx, id).await?;
+    set_status(&mut tx, before.clone(), RequisitionStatus::Fulfilled).await?;
+
+    let requisition = sqlx::query_as!(
+        RequisitionEntity,
+        r#"UPDATE requisitions SET place_id = $2 WHERE id = $1
+        RETURNING id, user_id, status as "status: RequisitionStatus", note, place_id, approver_id, created_at, updated_at"#,
+        id,
+        data.place_id
+    )
+    .fetch_one(&mut tx)
+    .await
+    .on_constraint("requisitions_place_id_fkey", "place does not exist")?;
+
+    let lines = get_lines(&mut tx, id).await?;
+    for line in lines
+        .iter()
+        .filter(|l| l.status == RequisitionLineStatus::Approved)
+    {
+        let movement = NewMovement {
+            item_id: line.item_id,
+            place_id: data.place_id,
+            kind: MovementKind::Issue,
+            quantity: -line.quantity,
+            note: Some(format!("fulfilment of requisition {}", id)),
+            user_id,
+            transfer_id: None,
+            loan_id: None,
+            requisition_id: Some(id),
+        };
+        stock_service::apply_movement(&mut tx, movement).await?;
+    }
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::Requisition,
+        id,
+        Some(&before),
+        Some(&requisition),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(RequisitionBody { requisition, lines })
+}
+
+async fn get_lines<'e, E>(db: E, requisition_id: i32) -> Result<Vec<RequisitionLineEntity>>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let lines = sqlx::query_as!(
+        RequisitionLineEntity,
+        r#"SELECT id, requisition_id, item_id, quantity, status as "status: RequisitionLineStatus"
+        FROM requisition_lines WHERE requisition_id = $1 ORDER BY id"#,
+        requisition_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(lines)
+}
+
+async fn lock_requisition(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i32,
+) -> Result<RequisitionEntity> {
+    let requisition = sqlx::query_as!(
+        RequisitionEntity,
+        r#"SELECT id, user_id, status as "status: RequisitionStatus", note, place_id, approver_id, created_at, updated_at
+        FROM requisitions WHERE id = $1 FOR UPDATE"#,
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(CustomError::NotFound)?;
+
+    Ok(requisition)
+}
+
+/// Moves a locked requisition to `next`, refusing edges the state machine
+/// does not have.
+async fn set_status(
+    tx: &mut Transaction<'_, Postgres>,
+    requisition: RequisitionEntity,
+    next: RequisitionStatus,
+) -> Result<RequisitionEntity> {
+    if !requisition.status.can_transition_to(next) {
+        return Err(CustomError::InvalidTransition {
+            from: requisition.status.as_str(),
+            to: next.as_str(),
+        });
+    }
+
+    let requisition = sqlx::query_as!(
+        RequisitionEntity,
+        r#"UPDATE requisitions SET status = $2 WHERE id = $1
+        RETURNING id, user_id, status as "status: RequisitionStatus", note, place_id, approver_id, created_at, updated_at"#,
+        requisition.id,
+        next as RequisitionStatus
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    Ok(requisition)
+}
@@ -0,0 +1,99 @@
+use crate::{
+    models::search_model::{SearchHit, SearchKind, SearchQuery},
+    validation::field_error,
+    Result,
+};
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+/// Searches places and items, best matches first. Every word matches as a
+/// prefix ignoring accents, names that are merely close to the query still
+/// match so typos don't come back empty, and items are found by their SKU.
+pub async fn search(db: &sqlx::Pool<sqlx::Postgres>, query: SearchQuery) -> Result<Vec<SearchHit>> {
+    let text = query.q.trim();
+    if text.is_empty() {
+        return Err(field_error("q", "can not be empty"));
+    }
+    if text.chars().count() > 255 {
+        return Err(field_error("q", "must have at most 255 characters"));
+    }
+
+    let hits = sqlx::query_as!(
+        SearchHit,
+        r#"WITH query AS (
+            SELECT to_tsquery('almox', immutable_unaccent($1)) AS tsquery,
+                immutable_unaccent(LOWER($2)) AS text
+        )
+        SELECT kind as "kind!", id as "id!", name as "name!", snippet as "snippet!", rank as "rank!"
+        FROM (
+            SELECT 'place' AS kind, p.id, p.name,
+                ts_headline('almox', html_escape(p.name || COALESCE(' - ' || p.description, '')), q.tsquery,
+                    'StartSel=<mark>, StopSel=</mark>, HighlightAll=TRUE') AS snippet,
+                ts_rank(place_document(p.name, p.description), q.tsquery)
+                    + word_similarity(q.text, immutable_unaccent(LOWER(p.name))) AS rank
+            FROM places p, query q
+            WHERE $3::TEXT IS DISTINCT FROM 'item' AND p.deleted_at IS NULL
+                AND (place_document(p.name, p.description) @@ q.tsquery
+                    OR q.text <% immutable_unaccent(LOWER(p.name)))
+            UNION ALL
+            SELECT 'item', i.id, i.name,
+                ts_headline('almox', html_escape(i.name || COALESCE(' - ' || i.description, '')), q.tsquery,
+                    'StartSel=<mark>, StopSel=</mark>, HighlightAll=TRUE'),
+                ts_rank(item_document(i.name, i.sku, i.description), q.tsquery)
+                    + word_similarity(q.text, immutable_unaccent(LOWER(i.name)))
+                    + (LOWER(i.sku) = LOWER($2))::INT::REAL
+            FROM items i, query q
+            WHERE $3::TEXT IS DISTINCT FROM 'place'
+                AND (item_document(i.name, i.sku, i.description) @@ q.tsquery
+                    OR q.text <% immutable_unaccent(LOWER(i.name))
+                    OR LOWER(i.sku) = LOWER($2))
+        ) hits
+        ORDER BY rank DESC, kind, id
+        LIMIT $4"#,
+        prefix_query(text),
+        text,
+        query.kind.map(|kind| match kind {
+            SearchKind::Place => "place",
+            SearchKind::Item => "item",
+        }),
+        query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(hits)
+}
+
+/// Turns what the user typed into a `tsquery` where every word must match as
+/// a prefix. Anything but letters and digits is dropped, so the input can't
+/// inject `tsquery` operators.
+fn prefix_query(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("{}:*", word))
+        .collect::<Vec<_>>()
+        .join(" & ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_query_matches_every_word_as_a_prefix() {
+        assert_eq!(prefix_query("parafuso"), "parafuso:*");
+        assert_eq!(
+            prefix_query("  parafuso   sextavado "),
+            "parafuso:* & sextavado:*"
+        );
+        assert_eq!(prefix_query("almoxarifádo"), "almoxarifádo:*");
+    }
+
+    #[test]
+    fn prefix_query_drops_operators() {
+        assert_eq!(prefix_query("a & !b | (c:*)"), "a:* & b:* & c:*");
+        assert_eq!(prefix_query("M8-x30"), "M8:* & x30:*");
+        assert_eq!(prefix_query("!&|"), "");
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    client::ClientInfo, models::session_model::SessionEntity, validation::CustomError, Result,
+};
+
+/// Opens a session for `user_id`, returning it with the refresh token that
+/// continues it. Only a hash of the token is kept.
+pub async fn create_session(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+    client: ClientInfo,
+    ttl: Duration,
+) -> Result<(SessionEntity, String)> {
+    let token = generate_token();
+
+    let session = sqlx::query_as!(
+        SessionEntity,
+        "INSERT INTO sessions (user_id, refresh_token_hash, user_agent, ip, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, user_id, user_agent, ip, created_at, last_used_at, expires_at, revoked_at",
+        user_id,
+        hash_token(&token),
+        client.user_agent,
+        client.ip,
+        Utc::now() + ttl
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok((session, token))
+}
+
+/// Trades a refresh token for a new one, extending its session.
+///
+/// Every refresh token can be used only once. Presenting one that was already
+/// rotated means it leaked, so the whole session is revoked.
+pub async fn rotate_session(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    token: &str,
+    client: ClientInfo,
+    ttl: Duration,
+) -> Result<(SessionEntity, String)> {
+    let hash = hash_token(token);
+    let new_token = generate_token();
+
+    let session = sqlx::query_as!(
+        SessionEntity,
+        "UPDATE sessions SET
+            refresh_token_hash = $2,
+            previous_token_hash = refresh_token_hash,
+            user_agent = COALESCE($3, user_agent),
+            ip = COALESCE($4, ip),
+            last_used_at = NOW(),
+            expires_at = $5
+        WHERE refresh_token_hash = $1 AND revoked_at IS NULL AND expires_at > NOW()
+        RETURNING id, user_id, user_agent, ip, created_at, last_used_at, expires_at, revoked_at",
+        hash,
+        hash_token(&new_token),
+        client.user_agent,
+        client.ip,
+        Utc::now() + ttl
+    )
+    .fetch_optional(db)
+    .await?;
+
+    match session {
+        Some(session) => Ok((session, new_token)),
+        None => {
+            let reused = sqlx::query!(
+                "UPDATE sessions SET revoked_at = NOW()
+                WHERE previous_token_hash = $1 AND revoked_at IS NULL",
+                hash
+            )
+            .execute(db)
+            .await?;
+
+            if reused.rows_affected() > 0 {
+                tracing::warn!("Refresh token reused, session revoked");
+            }
+
+            Err(CustomError::Unauthorized)
+        }
+    }
+}
+
+pub async fn is_session_active(db: &sqlx::Pool<sqlx::Postgres>, id: i32) -> Result<bool> {
+    let active = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM sessions WHERE id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+        ) as "active!""#,
+        id
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(active)
+}
+
+pub async fn get_active_sessions(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+) -> Result<Vec<SessionEntity>> {
+    let sessions = sqlx::query_as!(
+        SessionEntity,
+        "SELECT id, user_id, user_agent, ip, created_at, last_used_at, expires_at, revoked_at
+        FROM sessions
+        WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+        ORDER BY last_used_at DESC",
+        user_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(sessions)
+}
+
+pub async fn get_session(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+) -> Result<Option<SessionEntity>> {
+    let session = sqlx::query_as!(
+        SessionEntity,
+        "SELECT id, user_id, user_agent, ip, created_at, last_used_at, expires_at, revoked_at
+        FROM sessions WHERE id = $1",
+        id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(session)
+}
+
+pub async fn revoke_session(db: &sqlx::Pool<sqlx::Postgres>, id: i32) -> Result<()> {
+    sqlx::query!(
+        "UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL",
+        id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn revoke_all_sessions(db: &sqlx::Pool<sqlx::Postgres>, user_id: i32) -> Result<()> {
+    sqlx::query!(
+        "UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+        user_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Revokes every session of the user but `keep`, the one making the request.
+pub async fn revoke_other_sessions(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+    keep: Option<i32>,
+) -> Result<()> {
+    sqlx::query!(
+        "UPDATE sessions SET revoked_at = NOW()
+        WHERE user_id = $1 AND revoked_at IS NULL AND id IS DISTINCT FROM $2",
+        user_id,
+        keep
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
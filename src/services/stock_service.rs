@@ -0,0 +1,343 @@
+use sqlx::{Postgres, Transaction};
+
+use crate::{
+    authorization::Actor,
+    etag::IfMatch,
+    models::{
+        audit_model::{AuditAction, AuditEntityType},
+        stock_model::{
+            CreateMovementDTO, CreateTransferDTO, MovementKind, MovementQuery, NewMovement,
+            StockLevelEntity, StockMovementEntity, StockTotal, TransferEntity,
+        },
+    },
+    services::audit_service,
+    validation::{field_error, CustomError, ResultExt},
+    Result,
+};
+
+/// What is kept in a place and in every place inside of it.
+pub async fn get_place_stock(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    place_id: i32,
+) -> Result<Vec<StockLevelEntity>> {
+    let levels = sqlx::query_as!(
+        StockLevelEntity,
+        "SELECT * FROM stock_levels WHERE quantity > 0 AND place_id IN (
+            WITH RECURSIVE subtree AS (
+                SELECT id FROM places WHERE id = $1
+                UNION
+                SELECT p.id FROM places p JOIN subtree s ON p.parent_id = s.id
+            )
+            SELECT id FROM subtree
+        )
+        ORDER BY place_id, item_id",
+        place_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(levels)
+}
+
+/// How much of each item a place holds counting the places inside of it.
+pub async fn get_place_totals(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    place_id: i32,
+) -> Result<Vec<StockTotal>> {
+    let totals = sqlx::query_as!(
+        StockTotal,
+        r#"SELECT item_id, SUM(quantity) as "quantity!" FROM stock_levels WHERE quantity > 0 AND place_id IN (
+            WITH RECURSIVE subtree AS (
+                SELECT id FROM places WHERE id = $1
+                UNION
+                SELECT p.id FROM places p JOIN subtree s ON p.parent_id = s.id
+            )
+            SELECT id FROM subtree
+        )
+        GROUP BY item_id
+        ORDER BY item_id"#,
+        place_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(totals)
+}
+
+/// The balance of one item at one place, `None` if it was never stocked there.
+pub async fn get_level(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    item_id: i32,
+    place_id: i32,
+) -> Result<Option<StockLevelEntity>> {
+    let level = sqlx::query_as!(
+        StockLevelEntity,
+        "SELECT * FROM stock_levels WHERE item_id = $1 AND place_id = $2",
+        item_id,
+        place_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(level)
+}
+
+pub async fn get_item_stock(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    item_id: i32,
+) -> Result<Vec<StockLevelEntity>> {
+    let levels = sqlx::query_as!(
+        StockLevelEntity,
+        "SELECT * FROM stock_levels WHERE item_id = $1 AND quantity > 0 ORDER BY place_id",
+        item_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(levels)
+}
+
+pub async fn get_movements(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    query: MovementQuery,
+) -> Result<Vec<StockMovementEntity>> {
+    let movements = sqlx::query_as!(
+        StockMovementEntity,
+        r#"SELECT id, item_id, place_id, kind as "kind: MovementKind", quantity, note, user_id, transfer_id, loan_id, requisition_id, created_at
+        FROM stock_movements
+        WHERE ($1::INTEGER IS NULL OR item_id = $1) AND ($2::INTEGER IS NULL OR place_id = $2)
+        ORDER BY id"#,
+        query.item_id,
+        query.place_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(movements)
+}
+
+pub async fn create_movement(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    data: CreateMovementDTO,
+    if_match: IfMatch,
+    user_id: i32,
+    actor: &Actor,
+) -> Result<StockMovementEntity> {
+    let mut tx = db.begin().await?;
+
+    // an adjustment is worked out from a count, which is only right as long
+    // as the level hasn't moved since; receipts and issues add to any level
+    if data.kind == MovementKind::Adjustment {
+        let level = sqlx::query_as!(
+            StockLevelEntity,
+            "SELECT * FROM stock_levels WHERE item_id = $1 AND place_id = $2 FOR UPDATE",
+            data.item_id,
+            data.place_id
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        if_match.check(level.as_ref())?;
+    }
+
+    let movement = apply_movement(&mut tx, data.into_movement(user_id)).await?;
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Create,
+        AuditEntityType::StockMovement,
+        movement.id,
+        None,
+        Some(&movement),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(movement)
+}
+
+/// Moves stock between two places: the source is debited and the destination
+/// credited in the same transaction, or nothing happens at all.
+pub async fn create_transfer(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    data: CreateTransferDTO,
+    user_id: i32,
+    actor: &Actor,
+) -> Result<TransferEntity> {
+    let mut tx = db.begin().await?;
+
+    // lock both balances in a fixed order so opposite transfers can't deadlock
+    ensure_level(&mut tx, data.item_id, data.from_place_id).await?;
+    ensure_level(&mut tx, data.item_id, data.to_place_id).await?;
+    sqlx::query!(
+        "SELECT quantity FROM stock_levels
+        WHERE item_id = $1 AND place_id IN ($2, $3)
+        ORDER BY place_id FOR UPDATE",
+        data.item_id,
+        data.from_place_id,
+        data.to_place_id
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    let transfer = sqlx::query_as!(
+        TransferEntity,
+        "INSERT INTO stock_transfers (item_id, from_place_id, to_place_id, quantity, note, user_id)
+        VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+        data.item_id,
+        data.from_place_id,
+        data.to_place_id,
+        data.quantity,
+        data.note,
+        user_id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    for (place_id, quantity) in [
+        (transfer.from_place_id, -transfer.quantity),
+        (transfer.to_place_id, transfer.quantity),
+    ] {
+        let movement = NewMovement {
+            item_id: transfer.item_id,
+            place_id,
+            kind: MovementKind::Transfer,
+            quantity,
+            note: transfer.note.clone(),
+            user_id,
+            transfer_id: Some(transfer.id),
+            loan_id: None,
+            requisition_id: None,
+        };
+        apply_movement(&mut tx, movement).await?;
+    }
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Create,
+        AuditEntityType::StockTransfer,
+        transfer.id,
+        None,
+        Some(&transfer),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(transfer)
+}
+
+pub async fn get_transfer(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    id: i32,
+) -> Result<Option<TransferEntity>> {
+    let transfer = sqlx::query_as!(
+        TransferEntity,
+        "SELECT * FROM stock_transfers WHERE id = $1",
+        id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(transfer)
+}
+
+/// Records a movement in the ledger and updates the balance it belongs to.
+///
+/// The balance row is locked for the rest of the transaction, so concurrent
+/// movements on the same item and place are serialized and can never take the
+/// balance below zero.
+pub(crate) async fn apply_movement(
+    tx: &mut Transaction<'_, Postgres>,
+    movement: NewMovement,
+) -> Result<StockMovementEntity> {
+    let NewMovement {
+        item_id,
+        place_id,
+        kind,
+        quantity: delta,
+        note,
+        user_id,
+        transfer_id,
+        loan_id,
+        requisition_id,
+    } = movement;
+
+    ensure_level(tx, item_id, place_id).await?;
+
+    let available = sqlx::query_scalar!(
+        "SELECT quantity FROM stock_levels WHERE item_id = $1 AND place_id = $2 FOR UPDATE",
+        item_id,
+        place_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let balance = available
+        .checked_add(delta)
+        .filter(|balance| *balance >= 0)
+        .ok_or(CustomError::InsufficientStock {
+            item_id,
+            place_id,
+            available,
+            requested: delta.saturating_neg(),
+        })?;
+
+    sqlx::query!(
+        "UPDATE stock_levels SET quantity = $3 WHERE item_id = $1 AND place_id = $2",
+        item_id,
+        place_id,
+        balance
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let movement = sqlx::query_as!(
+        StockMovementEntity,
+        r#"INSERT INTO stock_movements (item_id, place_id, kind, quantity, note, user_id, transfer_id, loan_id, requisition_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING id, item_id, place_id, kind as "kind: MovementKind", quantity, note, user_id, transfer_id, loan_id, requisition_id, created_at"#,
+        item_id,
+        place_id,
+        kind as MovementKind,
+        delta,
+        note,
+        user_id,
+        transfer_id,
+        loan_id,
+        requisition_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    Ok(movement)
+}
+
+/// Makes sure the balance row for the item and place exists so it can be locked.
+async fn ensure_level(
+    tx: &mut Transaction<'_, Postgres>,
+    item_id: i32,
+    place_id: i32,
+) -> Result<()> {
+    let deleted = sqlx::query_scalar!(
+        r#"SELECT deleted_at IS NOT NULL as "deleted!" FROM places WHERE id = $1"#,
+        place_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if deleted == Some(true) {
+        return Err(field_error("place_id", "place was deleted"));
+    }
+
+    sqlx::query!(
+        "INSERT INTO stock_levels (item_id, place_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        item_id,
+        place_id
+    )
+    .execute(&mut *tx)
+    .await
+    .on_constraint("stock_levels_item_id_fkey", "item does not exist")
+    .on_constraint("stock_levels_place_id_fkey", "place does not exist")?;
+
+    Ok(())
+}
@@ -0,0 +1,402 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use chrono::Duration;
+use rand::RngCore;
+use serde_json::json;
+use sqlx::{Postgres, Transaction};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::{
+    authorization::{ActionClaims, Actor},
+    models::{
+        account_model::TokenPurpose,
+        audit_model::{AuditAction, AuditEntityType},
+        profile_model::ProfileEntity,
+        two_factor_model::{
+            EnrolmentBody, RecoveryCodesBody, RolePolicyEntity, UpdateRolePolicyDTO,
+        },
+        user_model::Role,
+    },
+    services::{account_service, audit_service, session_service, user_service},
+    validation::{field_error, CustomError},
+    AppState, Result,
+};
+
+const ISSUER: &str = "Almoxarifado";
+const RECOVERY_CODES: usize = 10;
+const CHALLENGE_TTL_MINUTES: i64 = 5;
+
+/// Stores a fresh secret for `user_id`, which only takes effect once a code
+/// generated from it is confirmed. Enrolling again replaces a pending secret.
+pub async fn enrol(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+    email: &str,
+) -> Result<EnrolmentBody> {
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let totp = totp(&secret, email)?;
+
+    let updated = sqlx::query!(
+        "UPDATE users SET totp_secret = $2, totp_last_step = NULL
+        WHERE id = $1 AND totp_enabled_at IS NULL",
+        user_id,
+        secret
+    )
+    .execute(db)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        return Err(field_error("two_factor", "already enabled"));
+    }
+
+    Ok(EnrolmentBody {
+        secret,
+        provisioning_uri: totp.get_url(),
+    })
+}
+
+/// Turns on the pending secret of `user_id` if `code` matches it, handing out
+/// the recovery codes. They are only ever shown this once.
+pub async fn confirm(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+    code: &str,
+    actor: &Actor,
+) -> Result<RecoveryCodesBody> {
+    let mut tx = db.begin().await?;
+    let before = user_service::lock_profile(&mut tx, user_id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+
+    let user = sqlx::query!(
+        "SELECT email, totp_secret, totp_enabled_at FROM users WHERE id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    let secret = match (user.totp_secret, user.totp_enabled_at) {
+        (_, Some(_)) => return Err(field_error("two_factor", "already enabled")),
+        (None, None) => return Err(field_error("two_factor", "not enrolled")),
+        (Some(secret), None) => secret,
+    };
+
+    if !accept_totp(&mut tx, user_id, &secret, &user.email, code).await? {
+        return Err(field_error("code", "invalid code"));
+    }
+
+    sqlx::query!(
+        "UPDATE users SET totp_enabled_at = NOW() WHERE id = $1",
+        user_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    let recovery_codes = replace_recovery_codes(&mut tx, user_id).await?;
+
+    record_change(&mut tx, actor, &before).await?;
+    tx.commit().await?;
+
+    Ok(RecoveryCodesBody { recovery_codes })
+}
+
+/// Checks `code` against the authenticator of `user_id`, falling back to its
+/// recovery codes. Accepted codes can't be used again.
+pub async fn verify_code(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+    code: &str,
+) -> Result<bool> {
+    let mut tx = db.begin().await?;
+
+    let user = sqlx::query!(
+        "SELECT email, totp_secret FROM users
+        WHERE id = $1 AND totp_enabled_at IS NOT NULL FOR UPDATE",
+        user_id
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+
+    let Some(user) = user else {
+        return Ok(false);
+    };
+    let secret = user
+        .totp_secret
+        .context("Two-factor enabled without a secret")?;
+
+    let accepted = accept_totp(&mut tx, user_id, &secret, &user.email, code).await?
+        || use_recovery_code(&mut tx, user_id, code).await?;
+
+    tx.commit().await?;
+
+    Ok(accepted)
+}
+
+pub async fn regenerate_recovery_codes(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i32,
+    actor: &Actor,
+) -> Result<RecoveryCodesBody> {
+    let mut tx = db.begin().await?;
+    let recovery_codes = replace_recovery_codes(&mut tx, user_id).await?;
+
+    // the codes themselves never make it to the log
+    audit_service::record_json(
+        &mut tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::User,
+        user_id,
+        Some(json!({})),
+        Some(json!({ "recovery_codes": "regenerated" })),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(RecoveryCodesBody { recovery_codes })
+}
+
+/// Drops the secret and recovery codes of `user_id`, also used by admins for
+/// users who lost their device.
+pub async fn disable(db: &sqlx::Pool<sqlx::Postgres>, user_id: i32, actor: &Actor) -> Result<()> {
+    let mut tx = db.begin().await?;
+    let before = user_service::lock_profile(&mut tx, user_id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+
+    sqlx::query!(
+        "UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL
+        WHERE id = $1",
+        user_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut tx)
+        .await?;
+
+    record_change(&mut tx, actor, &before).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Issues the short lived token a password login hands out instead of a
+/// session when the account has two-factor enabled.
+pub async fn issue_challenge(state: &AppState, user_id: i32) -> Result<String> {
+    account_service::issue_token(
+        state,
+        user_id,
+        TokenPurpose::TwoFactorLogin,
+        Duration::minutes(CHALLENGE_TTL_MINUTES),
+    )
+    .await
+}
+
+/// Tells who `challenge` was issued to without using it up, so a mistyped
+/// code doesn't force the user to enter their password again.
+pub fn challenge_user(state: &AppState, challenge: &str) -> Result<i32> {
+    ActionClaims::from_jwt(challenge, TokenPurpose::TwoFactorLogin, &state.config)
+        .map(|claims| claims.sub)
+        .ok_or_else(|| field_error("challenge", "invalid or expired token"))
+}
+
+pub async fn complete_challenge(state: &AppState, challenge: &str) -> Result<i32> {
+    account_service::use_token(state, challenge, TokenPurpose::TwoFactorLogin).await
+}
+
+pub async fn is_required(db: &sqlx::Pool<sqlx::Postgres>, role: Role) -> Result<bool> {
+    let required = sqlx::query_scalar!(
+        "SELECT require_two_factor FROM role_policies WHERE role = $1",
+        role as Role
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(required.unwrap_or(false))
+}
+
+pub async fn get_policies(db: &sqlx::Pool<sqlx::Postgres>) -> Result<Vec<RolePolicyEntity>> {
+    let policies = sqlx::query_as!(
+        RolePolicyEntity,
+        r#"SELECT id, role as "role: Role", require_two_factor, updated_at
+        FROM role_policies ORDER BY role"#
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(policies)
+}
+
+pub async fn update_policy(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    data: UpdateRolePolicyDTO,
+    actor: &Actor,
+) -> Result<RolePolicyEntity> {
+    let mut tx = db.begin().await?;
+
+    let before = sqlx::query_as!(
+        RolePolicyEntity,
+        r#"SELECT id, role as "role: Role", require_two_factor, updated_at
+        FROM role_policies WHERE role = $1 FOR UPDATE"#,
+        data.role as Role
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+
+    let policy = sqlx::query_as!(
+        RolePolicyEntity,
+        r#"INSERT INTO role_policies (role, require_two_factor) VALUES ($1, $2)
+        ON CONFLICT (role) DO UPDATE SET require_two_factor = EXCLUDED.require_two_factor
+        RETURNING id, role as "role: Role", require_two_factor, updated_at"#,
+        data.role as Role,
+        data.require_two_factor
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        if before.is_some() {
+            AuditAction::Update
+        } else {
+            AuditAction::Create
+        },
+        AuditEntityType::RolePolicy,
+        policy.id,
+        before.as_ref(),
+        Some(&policy),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(policy)
+}
+
+/// Audits turning two-factor on or off, `before` being the user as it was.
+async fn record_change(
+    tx: &mut Transaction<'_, Postgres>,
+    actor: &Actor,
+    before: &ProfileEntity,
+) -> Result<()> {
+    let after = user_service::lock_profile(tx, before.id)
+        .await?
+        .ok_or(CustomError::NotFound)?;
+
+    user_service::record_update(tx, actor, before, &after, false).await
+}
+
+fn totp(secret: &str, email: &str) -> Result<TOTP> {
+    let bytes = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("Could not decode TOTP secret: {:?}", e))?;
+
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        bytes,
+        Some(ISSUER.to_string()),
+        email.to_string(),
+    )
+    .map_err(|e| anyhow::anyhow!("Could not build TOTP: {:?}", e))?;
+
+    Ok(totp)
+}
+
+/// Accepts `code` if it belongs to the current time step, or one step off to
+/// allow for clock drift, and is newer than the last code accepted.
+async fn accept_totp(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: i32,
+    secret: &str,
+    email: &str,
+    code: &str,
+) -> Result<bool> {
+    let totp = totp(secret, email)?;
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("System clock before epoch")?
+        .as_secs();
+
+    let step = [now - totp.step, now, now + totp.step]
+        .into_iter()
+        .find(|&time| totp.generate(time) == code)
+        .map(|time| (time / totp.step) as i64);
+
+    let Some(step) = step else {
+        return Ok(false);
+    };
+
+    let updated = sqlx::query!(
+        "UPDATE users SET totp_last_step = $2
+        WHERE id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)",
+        user_id,
+        step
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(updated.rows_affected() == 1)
+}
+
+async fn use_recovery_code(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: i32,
+    code: &str,
+) -> Result<bool> {
+    let used = sqlx::query!(
+        "UPDATE recovery_codes SET used_at = NOW()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+        user_id,
+        session_service::hash_token(&normalize_recovery_code(code))
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(used.rows_affected() == 1)
+}
+
+async fn replace_recovery_codes(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: i32,
+) -> Result<Vec<String>> {
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let codes: Vec<String> = (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let code = hex::encode(bytes);
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect();
+    let hashes: Vec<String> = codes
+        .iter()
+        .map(|code| session_service::hash_token(&normalize_recovery_code(code)))
+        .collect();
+
+    sqlx::query!(
+        "INSERT INTO recovery_codes (user_id, code_hash) SELECT $1, UNNEST($2::VARCHAR[])",
+        user_id,
+        &hashes
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(codes)
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
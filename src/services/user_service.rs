@@ -1,36 +1,70 @@
 use crate::{
+    authorization::Actor,
+    etag::IfMatch,
     models::{
+        audit_model::{AuditAction, AuditEntityType},
         profile_model::ProfileEntity,
-        user_model::{CreateUserDTO, LoginUserDTO, UpdateUserDTO, UserEntity},
+        user_model::{CreateUserDTO, LoginUserDTO, Role, UpdateUserDTO, UserEntity},
     },
-    validation::ResultExt,
+    password,
+    services::{audit_service, session_service},
+    validation::{field_error, ResultExt},
 };
 use crate::{validation::CustomError, Result};
 use anyhow::Context;
 use argon2::{password_hash::SaltString, PasswordHasher, PasswordVerifier};
 use argon2::{Argon2, PasswordHash};
+use sqlx::{Postgres, Transaction};
+
+/// Taken while creating a user, see [`lock_bootstrap`].
+const BOOTSTRAP_LOCK: i64 = 0x0075_7365_7273;
 
 pub async fn create_user(
     user: CreateUserDTO,
+    actor: &Actor,
     state: &sqlx::Pool<sqlx::Postgres>,
 ) -> Result<ProfileEntity> {
+    password::ensure_not_email(&user.password, &user.email)?;
     let pass_hash = hash_password(user.password).await?;
+    let mut tx = state.begin().await?;
 
-    let user_id = sqlx::query_scalar!(
-        "INSERT INTO users (name, email, password) VALUES ($1, $2, $3) RETURNING id",
+    // the very first account bootstraps the installation as its admin
+    lock_bootstrap(&mut tx).await?;
+    let created = sqlx::query!(
+        r#"INSERT INTO users (name, email, password, role)
+        VALUES ($1, $2, $3, CASE WHEN EXISTS (SELECT 1 FROM users) THEN 'requester' ELSE 'admin' END::user_role)
+        RETURNING id, role as "role: Role""#,
         user.name,
         user.email,
         pass_hash
     )
-    .fetch_one(state)
+    .fetch_one(&mut tx)
     .await
     .on_constraint("users_email_key", "email already taken")?;
 
-    Ok(ProfileEntity {
-        id: user_id,
+    let profile = ProfileEntity {
+        id: created.id,
         name: user.name,
         email: user.email,
-    })
+        role: created.role,
+        verified: false,
+        two_factor: false,
+        deleted_at: None,
+    };
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Create,
+        AuditEntityType::User,
+        profile.id,
+        None,
+        Some(&profile),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(profile)
 }
 
 pub async fn login_user(
@@ -39,51 +73,323 @@ pub async fn login_user(
 ) -> Result<ProfileEntity> {
     let user = sqlx::query_as!(
         UserEntity,
-        "SELECT * FROM users WHERE email = $1",
+        r#"SELECT id, name, email, password, role as "role: Role", email_verified_at, totp_enabled_at,
+            created_at, updated_at
+        FROM users WHERE email = $1 AND deleted_at IS NULL"#,
         req.email
     )
     .fetch_optional(state)
     .await?
     .ok_or(CustomError::Unauthorized)?;
 
-    verify_password(req.password, user.password).await?;
+    let hash = user.password.ok_or(CustomError::Unauthorized)?;
+    let needs_rehash = verify_password(req.password.clone(), hash).await?;
+
+    // the costs were changed since this hash was made, now is the only time
+    // we have the password to bring it up to date
+    if needs_rehash {
+        match hash_password(req.password).await {
+            Ok(pass_hash) => {
+                sqlx::query!(
+                    "UPDATE users SET password = $2 WHERE id = $1",
+                    user.id,
+                    pass_hash
+                )
+                .execute(state)
+                .await?;
+            }
+            Err(e) => tracing::error!("Could not rehash password: {:?}", e),
+        }
+    }
 
     Ok(ProfileEntity {
         id: user.id,
         name: user.name,
         email: user.email,
+        role: user.role,
+        verified: user.email_verified_at.is_some(),
+        two_factor: user.totp_enabled_at.is_some(),
+        deleted_at: None,
     })
 }
 
+/// Changing one's own password takes the current one, and either way ends
+/// every other session of the user. `session` is the one of the caller, which
+/// is kept.
 pub async fn update_user(
     id: i32,
     data: UpdateUserDTO,
+    if_match: &IfMatch,
+    session: Option<i32>,
+    actor: &Actor,
     state: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<UserEntity> {
+) -> Result<Option<ProfileEntity>> {
+    let pass_hash = match data.password {
+        Some(password) => {
+            let current = sqlx::query!(
+                "SELECT email, password FROM users WHERE id = $1 AND deleted_at IS NULL",
+                id
+            )
+            .fetch_optional(state)
+            .await?;
+            let Some(current) = current else {
+                return Ok(None);
+            };
+            // a stolen access token must not be enough to take the account over
+            if actor.user_id == Some(id) {
+                let Some(current_password) = data.current_password else {
+                    return Err(field_error("current_password", "is required"));
+                };
+                let Some(hash) = current.password else {
+                    return Err(field_error(
+                        "current_password",
+                        "account has no password, reset it by email instead",
+                    ));
+                };
+                match verify_password(current_password, hash).await {
+                    Err(CustomError::Unauthorized) => {
+                        return Err(field_error("current_password", "wrong password"))
+                    }
+                    result => result?,
+                };
+            }
+            password::ensure_not_email(&password, &current.email)?;
+            Some(hash_password(password).await?)
+        }
+        None => None,
+    };
+
+    let mut tx = state.begin().await?;
+    let Some(before) = lock_profile(&mut tx, id).await? else {
+        return Ok(None);
+    };
+    if_match.check(Some(&before))?;
+
     let user = sqlx::query_as!(
-        UserEntity,
-        "UPDATE users SET name = $2, email = $3 WHERE id = $1 RETURNING *",
+        ProfileEntity,
+        r#"UPDATE users SET name = COALESCE($2, name), password = COALESCE($3, password) WHERE id = $1
+        RETURNING id, name, email, role as "role: Role", email_verified_at IS NOT NULL as "verified!",
+        totp_enabled_at IS NOT NULL as "two_factor!", deleted_at"#,
         id,
         data.name,
-        data.password,
+        pass_hash,
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    record_update(&mut tx, actor, &before, &user, pass_hash.is_some()).await?;
+    tx.commit().await?;
+
+    if pass_hash.is_some() {
+        session_service::revoke_other_sessions(state, id, session).await?;
+    }
+
+    Ok(Some(user))
+}
+
+pub async fn update_role(
+    id: i32,
+    role: Role,
+    if_match: &IfMatch,
+    actor: &Actor,
+    state: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Option<ProfileEntity>> {
+    let mut tx = state.begin().await?;
+    let Some(before) = lock_profile(&mut tx, id).await? else {
+        return Ok(None);
+    };
+    if_match.check(Some(&before))?;
+
+    let user = sqlx::query_as!(
+        ProfileEntity,
+        r#"UPDATE users SET role = $2 WHERE id = $1
+        RETURNING id, name, email, role as "role: Role", email_verified_at IS NOT NULL as "verified!",
+        totp_enabled_at IS NOT NULL as "two_factor!", deleted_at"#,
+        id,
+        role as Role
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    record_update(&mut tx, actor, &before, &user, false).await?;
+    tx.commit().await?;
+
+    Ok(Some(user))
+}
+
+/// Deletes the account but keeps its row for the history that points at it.
+/// Its sessions end right away and it can no longer log in.
+pub async fn delete_user(
+    id: i32,
+    if_match: &IfMatch,
+    actor: &Actor,
+    state: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<()> {
+    let mut tx = state.begin().await?;
+
+    let user = lock_profile(&mut tx, id).await?;
+    if_match.check(user.as_ref())?;
+    let Some(user) = user else {
+        return Ok(());
+    };
+
+    sqlx::query!("UPDATE users SET deleted_at = NOW() WHERE id = $1", id)
+        .execute(&mut tx)
+        .await?;
+
+    audit_service::record(
+        &mut tx,
+        actor,
+        AuditAction::Delete,
+        AuditEntityType::User,
+        user.id,
+        Some(&user),
+        None,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    session_service::revoke_all_sessions(state, id).await?;
+
+    Ok(())
+}
+
+/// Brings back a deleted account, `None` if there is no such deleted user.
+pub async fn restore_user(
+    id: i32,
+    actor: &Actor,
+    state: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Option<ProfileEntity>> {
+    let mut tx = state.begin().await?;
+
+    let user = sqlx::query_as!(
+        ProfileEntity,
+        r#"UPDATE users SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL
+        RETURNING id, name, email, role as "role: Role", email_verified_at IS NOT NULL as "verified!",
+        totp_enabled_at IS NOT NULL as "two_factor!", deleted_at"#,
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await
+    .on_constraint("users_email_key", "email taken by another account")?;
+
+    if let Some(user) = &user {
+        audit_service::record(
+            &mut tx,
+            actor,
+            AuditAction::Restore,
+            AuditEntityType::User,
+            user.id,
+            None,
+            Some(user),
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(user)
+}
+
+/// The profile of a user that wasn't deleted, locked for the rest of the transaction.
+pub(crate) async fn lock_profile(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i32,
+) -> Result<Option<ProfileEntity>> {
+    let user = sqlx::query_as!(
+        ProfileEntity,
+        r#"SELECT id, name, email, role as "role: Role", email_verified_at IS NOT NULL as "verified!",
+        totp_enabled_at IS NOT NULL as "two_factor!", deleted_at
+        FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE"#,
+        id
     )
-    .fetch_one(state)
+    .fetch_optional(&mut *tx)
     .await?;
+
     Ok(user)
 }
 
-pub async fn delete_user(id: i32, state: &sqlx::Pool<sqlx::Postgres>) -> Result<()> {
-    sqlx::query!("DELETE FROM users WHERE id = $1", id)
-        .execute(state)
+/// Serializes the signups that could be the first one, which is made admin.
+/// Without it two of them at once would both find no users and both be.
+pub(crate) async fn lock_bootstrap(tx: &mut Transaction<'_, Postgres>) -> Result<()> {
+    sqlx::query!("SELECT pg_advisory_xact_lock($1)", BOOTSTRAP_LOCK)
+        .execute(&mut *tx)
         .await?;
 
     Ok(())
 }
 
-async fn hash_password(password: String) -> Result<String> {
+/// Makes the user with that email address an admin, for installations that
+/// have none such as those older than roles. `None` if there is no such user.
+pub async fn promote_admin(
+    email: &str,
+    actor: &Actor,
+    state: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Option<ProfileEntity>> {
+    let mut tx = state.begin().await?;
+
+    let id = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE LOWER(email) = LOWER($1) AND deleted_at IS NULL",
+        email
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+    let Some(id) = id else {
+        return Ok(None);
+    };
+    let Some(before) = lock_profile(&mut tx, id).await? else {
+        return Ok(None);
+    };
+
+    let user = sqlx::query_as!(
+        ProfileEntity,
+        r#"UPDATE users SET role = 'admin' WHERE id = $1
+        RETURNING id, name, email, role as "role: Role", email_verified_at IS NOT NULL as "verified!",
+        totp_enabled_at IS NOT NULL as "two_factor!", deleted_at"#,
+        id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    record_update(&mut tx, actor, &before, &user, false).await?;
+    tx.commit().await?;
+
+    Ok(Some(user))
+}
+
+/// Audits a change to a user. A new password is flagged as such, its hash
+/// never makes it to the log.
+pub(crate) async fn record_update(
+    tx: &mut Transaction<'_, Postgres>,
+    actor: &Actor,
+    before: &ProfileEntity,
+    after: &ProfileEntity,
+    password_changed: bool,
+) -> Result<()> {
+    let id = before.id;
+    let before = serde_json::to_value(before).map_err(anyhow::Error::from)?;
+    let mut after = serde_json::to_value(after).map_err(anyhow::Error::from)?;
+    if password_changed {
+        after["password"] = "changed".into();
+    }
+
+    audit_service::record_json(
+        tx,
+        actor,
+        AuditAction::Update,
+        AuditEntityType::User,
+        id,
+        Some(before),
+        Some(after),
+    )
+    .await
+}
+
+pub(crate) async fn hash_password(password: String) -> Result<String> {
     tokio::task::spawn_blocking(move || -> Result<String> {
         let salt = SaltString::generate(rand::thread_rng());
-        Ok(Argon2::default()
+        Ok(password::hasher()
             .hash_password(password.as_bytes(), &salt)
             .map_err(|e| anyhow::anyhow!("Could not hash password: {}", e))?
             .to_string())
@@ -92,16 +398,20 @@ async fn hash_password(password: String) -> Result<String> {
     .context("Panic in generating password hash")?
 }
 
-async fn verify_password(password: String, hash: String) -> Result<()> {
-    tokio::task::spawn_blocking(move || -> Result<()> {
+/// Checks `password` against `hash`, telling whether the hash is due for a rehash.
+async fn verify_password(password: String, hash: String) -> Result<bool> {
+    tokio::task::spawn_blocking(move || -> Result<bool> {
         let parsed_hash = PasswordHash::new(&hash)
             .map_err(|e| anyhow::anyhow!("Could not parse password hash: {}", e))?;
+        // the hash carries its own parameters, so any of them verify
         Argon2::default()
             .verify_password(password.as_bytes(), &parsed_hash)
             .map_err(|e| match e {
                 argon2::password_hash::Error::Password => CustomError::Unauthorized,
-                _ => anyhow::anyhow!("Could not verify password: {}", e).into(),
-            })
+                _ => CustomError::Anyhow(anyhow::anyhow!("Could not verify password: {}", e)),
+            })?;
+
+        Ok(password::needs_rehash(&parsed_hash))
     })
     .await
     .context("Panic in verifying password")?
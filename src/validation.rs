@@ -1,14 +1,23 @@
+use std::borrow::Cow;
+
 use anyhow::Result;
 use axum::{
     async_trait,
-    extract::{rejection::JsonRejection, FromRequest},
-    http::{Request, StatusCode},
+    extract::{
+        multipart::{MultipartError, MultipartRejection},
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest, FromRequestParts,
+    },
+    http::{header::RETRY_AFTER, request::Parts, Request, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use sqlx::{error::DatabaseError, postgres::PgDatabaseError};
 use thiserror::Error;
-use validator::Validate;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::problem::{self, Problem};
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct ValidatedRequest<T>(pub T);
@@ -30,6 +39,44 @@ where
     }
 }
 
+/// Like [`axum::extract::Path`], but refused with a [`CustomError`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = CustomError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) =
+            axum::extract::Path::<T>::from_request_parts(parts, state).await?;
+        Ok(Path(value))
+    }
+}
+
+/// Like [`axum::extract::Query`], but refused with a [`CustomError`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = CustomError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) =
+            axum::extract::Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Query(value))
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum CustomError {
     #[error("Authentication required")]
@@ -38,15 +85,71 @@ pub enum CustomError {
     #[error("User may not perform that action")]
     Forbidden,
 
+    #[error("Email address not verified")]
+    EmailNotVerified,
+
+    #[error("Two-factor authentication must be set up first")]
+    TwoFactorRequired,
+
+    #[error("Password login is disabled, use single sign-on")]
+    PasswordLoginDisabled,
+
     #[error("Resource not found")]
     NotFound,
 
+    #[error("Too many attempts, try again in {retry_after} seconds")]
+    TooManyRequests { retry_after: i64 },
+
+    #[error("Insufficient stock of item {item_id} at place {place_id}: {available} available, {requested} requested")]
+    InsufficientStock {
+        item_id: i32,
+        place_id: i32,
+        available: i32,
+        requested: i32,
+    },
+
+    #[error("Can not go from {from} to {to}")]
+    InvalidTransition {
+        from: &'static str,
+        to: &'static str,
+    },
+
+    #[error("Lines of a {status} requisition can not be {decision}")]
+    LineNotDecidable {
+        status: &'static str,
+        decision: &'static str,
+    },
+
+    #[error("Resource was changed since it was fetched")]
+    PreconditionFailed,
+
+    #[error("If-Match header is required")]
+    PreconditionRequired,
+
+    #[error("File is larger than {limit} bytes")]
+    PayloadTooLarge { limit: usize },
+
+    #[error("Only JPEG and PNG images are accepted")]
+    UnsupportedMediaType,
+
     #[error(transparent)]
     ValidationError(#[from] validator::ValidationErrors),
 
     #[error(transparent)]
     AxumJsonRejection(#[from] JsonRejection),
 
+    #[error(transparent)]
+    AxumQueryRejection(#[from] QueryRejection),
+
+    #[error(transparent)]
+    AxumPathRejection(#[from] PathRejection),
+
+    #[error(transparent)]
+    AxumMultipartRejection(#[from] MultipartRejection),
+
+    #[error(transparent)]
+    AxumMultipartError(#[from] MultipartError),
+
     #[error("an database error occurred")]
     Sqlx(#[from] sqlx::Error),
 
@@ -58,30 +161,102 @@ impl CustomError {
     fn status_code(&self) -> StatusCode {
         match self {
             Self::Unauthorized => StatusCode::UNAUTHORIZED,
-            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::Forbidden
+            | Self::EmailNotVerified
+            | Self::TwoFactorRequired
+            | Self::PasswordLoginDisabled => StatusCode::FORBIDDEN,
             Self::NotFound => StatusCode::NOT_FOUND,
-            Self::ValidationError(_) | Self::AxumJsonRejection(_) => {
-                StatusCode::UNPROCESSABLE_ENTITY
-            }
+            Self::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::InsufficientStock { .. }
+            | Self::InvalidTransition { .. }
+            | Self::LineNotDecidable { .. } => StatusCode::CONFLICT,
+            Self::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Self::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            Self::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::ValidationError(_)
+            | Self::AxumJsonRejection(_)
+            | Self::AxumQueryRejection(_)
+            | Self::AxumPathRejection(_)
+            | Self::AxumMultipartRejection(_)
+            | Self::AxumMultipartError(_) => StatusCode::UNPROCESSABLE_ENTITY,
             Self::Sqlx(_) | Self::Anyhow(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
+impl CustomError {
+    /// Stable for each kind of error, unlike the messages.
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden => "forbidden",
+            Self::EmailNotVerified => "email_not_verified",
+            Self::TwoFactorRequired => "two_factor_required",
+            Self::PasswordLoginDisabled => "password_login_disabled",
+            Self::NotFound => "not_found",
+            Self::TooManyRequests { .. } => "too_many_requests",
+            Self::InsufficientStock { .. } => "insufficient_stock",
+            Self::InvalidTransition { .. } => "invalid_transition",
+            Self::LineNotDecidable { .. } => "line_not_decidable",
+            Self::PreconditionFailed => "precondition_failed",
+            Self::PreconditionRequired => "precondition_required",
+            Self::PayloadTooLarge { .. } => "payload_too_large",
+            Self::UnsupportedMediaType => "unsupported_media_type",
+            Self::ValidationError(_) => "validation_failed",
+            Self::AxumJsonRejection(_) => "invalid_json",
+            Self::AxumQueryRejection(_) => "invalid_query",
+            Self::AxumPathRejection(_) => "invalid_path",
+            Self::AxumMultipartRejection(_) | Self::AxumMultipartError(_) => "invalid_multipart",
+            Self::Sqlx(_) | Self::Anyhow(_) => "internal_error",
+        }
+    }
+}
+
 impl IntoResponse for CustomError {
     fn into_response(self) -> Response {
-        match self {
+        let detail = match &self {
+            CustomError::ValidationError(_) => self.to_string().replace('\n', ", "),
+            CustomError::AxumJsonRejection(rejection) => rejection.body_text(),
+            CustomError::AxumQueryRejection(rejection) => rejection.body_text(),
+            CustomError::AxumPathRejection(rejection) => rejection.body_text(),
+            _ => self.to_string(),
+        };
+        let problem = Problem::new(self.status_code(), self.code(), detail);
+        let problem = match &self {
             CustomError::Sqlx(_) | CustomError::Anyhow(_) => {
-                tracing::error!("{:?}", self);
-                (self.status_code(), "INTERNAL SERVER ERROR".to_string())
+                tracing::error!(request_id = problem::request_id(), "{:?}", self);
+                problem
             }
-            CustomError::ValidationError(_) => {
-                let message = format!("Input validation error: [{}]", self).replace('\n', ", ");
-                (self.status_code(), message)
+            CustomError::ValidationError(errors) => problem.with_field_errors(errors),
+            CustomError::TooManyRequests { retry_after } => {
+                return (
+                    [(RETRY_AFTER, retry_after.to_string())],
+                    problem.with("retry_after", *retry_after),
+                )
+                    .into_response();
             }
-            _ => (self.status_code(), self.to_string()),
-        }
-        .into_response()
+            CustomError::InsufficientStock {
+                item_id,
+                place_id,
+                available,
+                requested,
+            } => problem
+                .with("item_id", *item_id)
+                .with("place_id", *place_id)
+                .with("available", *available)
+                .with("requested", *requested),
+            CustomError::InvalidTransition { from, to } => {
+                problem.with("from", *from).with("to", *to)
+            }
+            CustomError::LineNotDecidable { status, decision } => {
+                problem.with("status", *status).with("decision", *decision)
+            }
+            CustomError::PayloadTooLarge { limit } => problem.with("limit", *limit),
+            _ => problem,
+        };
+
+        problem.into_response()
     }
 }
 
@@ -96,11 +271,117 @@ where
     fn on_constraint(self, name: &'static str, err_msg: &'static str) -> Result<T, CustomError> {
         self.map_err(|e| match e.into() {
             CustomError::Sqlx(sqlx::Error::Database(dbe)) if dbe.constraint() == Some(name) => {
-                let mut errors = validator::ValidationErrors::new();
-                errors.add(name, validator::ValidationError::new(err_msg));
-                CustomError::ValidationError(errors)
+                constraint_error(&*dbe, name, err_msg)
             }
             e => e,
         })
     }
 }
+
+/// The field error a violated constraint stands for. Constraints named the
+/// Postgres way, `<table>_<column>_key`, are reported on their column.
+fn constraint_error(
+    dbe: &dyn DatabaseError,
+    name: &'static str,
+    err_msg: &'static str,
+) -> CustomError {
+    let code = match dbe.code().as_deref() {
+        Some("23505") => "unique",
+        Some("23503") => "foreign_key",
+        Some("23514") => "check",
+        _ => "constraint",
+    };
+    let field = dbe
+        .try_downcast_ref::<PgDatabaseError>()
+        .and_then(|pg| pg.table())
+        .and_then(|table| name.strip_prefix(table)?.strip_prefix('_'))
+        .and_then(|column| {
+            ["_key", "_fkey", "_check"]
+                .iter()
+                .find_map(|suffix| column.strip_suffix(suffix))
+        })
+        .unwrap_or(name);
+
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::Borrowed(err_msg));
+    error.add_param(Cow::Borrowed("constraint"), &name);
+
+    let mut errors = ValidationErrors::new();
+    errors.add(field, error);
+    CustomError::ValidationError(errors)
+}
+
+/// A validation error on a single field, for checks that only the services can make.
+pub fn field_error(field: &'static str, err_msg: &'static str) -> CustomError {
+    let mut errors = ValidationErrors::new();
+    errors.add(field, invalid(err_msg));
+    CustomError::ValidationError(errors)
+}
+
+/// A validation error for the checks written by hand rather than derived.
+pub fn invalid(err_msg: &'static str) -> ValidationError {
+    let mut error = ValidationError::new("invalid");
+    error.message = Some(Cow::Borrowed(err_msg));
+    error
+}
+
+/// Reads a field of a partial update that can be cleared, telling a left out
+/// field (`None`) from an explicit `null` (`Some(None)`). Goes along with
+/// `#[serde(default)]`, which is what kicks in when the field is left out.
+pub fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Reads a field of a partial update that can be left out but not cleared,
+/// refusing an explicit `null`. Goes along with `#[serde(default)]`.
+pub fn non_null<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct Update {
+        #[serde(default, deserialize_with = "super::nullable")]
+        description: Option<Option<String>>,
+        #[serde(default, deserialize_with = "super::non_null")]
+        name: Option<String>,
+    }
+
+    fn parse(json: &str) -> serde_json::Result<Update> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn nullable_tells_left_out_from_null() {
+        assert_eq!(parse("{}").unwrap().description, None);
+        assert_eq!(
+            parse(r#"{"description":null}"#).unwrap().description,
+            Some(None)
+        );
+        assert_eq!(
+            parse(r#"{"description":"Top shelf"}"#).unwrap().description,
+            Some(Some("Top shelf".to_string()))
+        );
+    }
+
+    #[test]
+    fn non_null_refuses_null() {
+        assert_eq!(parse("{}").unwrap().name, None);
+        assert_eq!(
+            parse(r#"{"name":"Shelf"}"#).unwrap().name,
+            Some("Shelf".to_string())
+        );
+        assert!(parse(r#"{"name":null}"#).is_err());
+    }
+}